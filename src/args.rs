@@ -1,10 +1,32 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct PngMeArgs {
     #[clap(subcommand)]
     pub command_type: PnnmeFunctions,
+    /// Output format of the results
+    #[arg(long = "format", value_enum, global = true, default_value_t = Format::Text)]
+    pub format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Where encode hides the payload and decode looks for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// In private ancillary chunks of the type given by --type_chunk
+    Chunk,
+    /// In the lowest bits of the pixels
+    Lsb,
+    /// After the IEND chunk, where decoders don't look
+    Trailer,
+    /// In the IDAT chunks, after the end of the zlib stream of the pixels
+    IdatSlack,
 }
 
 #[derive(Debug, Subcommand)]
@@ -13,40 +35,247 @@ pub enum PnnmeFunctions {
     Decode(DecodeArgs),
     Remove(RemoveArgs),
     Print(PrintArgs),
+    Check(CheckArgs),
+    Keygen(KeygenArgs),
+    Sign(SignArgs),
+    Verify(VerifyArgs),
+    ListMessages(ListMessagesArgs),
+    Capacity(CapacityArgs),
+    Scan(ScanArgs),
 }
 
-/// pngme encode --file ./file.png --type_chunk ruSt --secret_message "This is a secret message!" 
+/// pngme encode --file ./file.png (--type_chunk ruSt | --lsb [--lsb-key key] [--lsb-bits 2] | --method trailer | --method idat-slack) (--secret_message "This is a secret message!" | --payload-file notes.txt) [--name notes] [--compress] [--encrypt | --recipient <key>...] [--in-place | --output out.png]
 #[derive(Debug, Args)]
 pub struct EncodeArgs {
+    /// PNG file to read, `-` for stdin
     #[arg(short = 'f', long = "file")]
     pub file_to_encode: std::path::PathBuf,
-    #[arg(short = 'c', long = "type_chunk")]
-    pub type_chunk: String,
-    #[arg(short = 'm', long = "secret_message")]
-    pub message_to_encode: String,
+    #[arg(
+        short = 'c',
+        long = "type_chunk",
+        required_unless_present_any = ["lsb", "method"],
+        conflicts_with = "lsb"
+    )]
+    pub type_chunk: Option<String>,
+    /// Hide the payload in the lowest bits of the pixels instead of a chunk, like --method lsb
+    #[arg(long = "lsb")]
+    pub lsb: bool,
+    /// Hide the payload with this method, chunk when --type_chunk is given
+    #[arg(long = "method", value_enum, conflicts_with = "lsb")]
+    pub method: Option<Method>,
+    /// Spread the bits over the pixels in an order drawn from this key
+    #[arg(long = "lsb-key")]
+    pub lsb_key: Option<String>,
+    /// Low bits of each colour sample used by --lsb, 1 by default
+    #[arg(long = "lsb-bits", value_parser = lsb_bits)]
+    pub lsb_bits: Option<u8>,
+    #[arg(
+        short = 'm',
+        long = "secret_message",
+        required_unless_present = "payload_file",
+        conflicts_with = "payload_file"
+    )]
+    pub message_to_encode: Option<String>,
+    /// File to hide instead of a message, with its name, size and hash
+    #[arg(long = "payload-file")]
+    pub payload_file: Option<std::path::PathBuf>,
+    /// Name of the message, to find it among the other messages of the same type
+    #[arg(long = "name", conflicts_with = "lsb")]
+    pub name: Option<String>,
+    /// Compress the payload with deflate before hiding it
+    #[arg(long = "compress")]
+    pub compress: bool,
+    /// Largest chunk to write, up to 2^31-1 bytes, a larger payload is split across several chunks
+    #[arg(long = "max-chunk-size", default_value_t = pngme::split::DEFAULT_PIECE_LEN)]
+    pub max_chunk_size: usize,
+    /// Encrypt the message with a passphrase, read from PNGME_PASSPHRASE or prompted
+    #[arg(long = "encrypt", conflicts_with = "recipients")]
+    pub encrypt: bool,
+    /// Encrypt the message for this public key, may be repeated
+    #[arg(short = 'r', long = "recipient")]
+    pub recipients: Vec<String>,
+    /// File to write, `-` for stdout
+    #[arg(short = 'o', long = "output", conflicts_with = "in_place")]
+    pub output: Option<std::path::PathBuf>,
+    /// Replace the file instead of writing a new one next to it
+    #[arg(short = 'i', long = "in-place")]
+    pub in_place: bool,
+    /// Keep the modification time of the file when editing it in place
+    #[arg(long = "preserve-mtime", requires = "in_place")]
+    pub preserve_mtime: bool,
 }
 
-/// pngme decode -f ./file.png -c ruSt
+/// pngme decode -f ./file.png (-c ruSt [--name notes] | --lsb [--lsb-key key] [--lsb-bits 2] | --method trailer | --method idat-slack) [--decrypt | --identity key.txt] [--extract-to dir]
 #[derive(Debug, Args)]
 pub struct DecodeArgs {
+    /// PNG file to read, `-` for stdin
     #[arg(short = 'f', long = "file")]
     pub file_with_message: std::path::PathBuf,
-    #[arg(short = 'c', long = "type_chunk")]
-    pub type_chunk: String,
+    #[arg(
+        short = 'c',
+        long = "type_chunk",
+        required_unless_present_any = ["lsb", "method"],
+        conflicts_with = "lsb"
+    )]
+    pub type_chunk: Option<String>,
+    /// Read the payload from the lowest bits of the pixels instead of a chunk, like --method lsb
+    #[arg(long = "lsb")]
+    pub lsb: bool,
+    /// Read the payload with this method, chunk when --type_chunk is given
+    #[arg(long = "method", value_enum, conflicts_with = "lsb")]
+    pub method: Option<Method>,
+    /// Key given to encode --lsb-key
+    #[arg(long = "lsb-key")]
+    pub lsb_key: Option<String>,
+    /// Bits per sample given to encode --lsb-bits, 1 by default
+    #[arg(long = "lsb-bits", value_parser = lsb_bits)]
+    pub lsb_bits: Option<u8>,
+    /// Decode the message with this name instead of the first one
+    #[arg(long = "name", conflicts_with = "lsb")]
+    pub name: Option<String>,
+    /// Decrypt the message with a passphrase, read from PNGME_PASSPHRASE or prompted
+    #[arg(long = "decrypt", conflicts_with = "identity")]
+    pub decrypt: bool,
+    /// Decrypt the message with the secret key of this file
+    #[arg(long = "identity")]
+    pub identity: Option<std::path::PathBuf>,
+    /// Write the hidden file there, or in this directory under its original name
+    #[arg(long = "extract-to")]
+    pub extract_to: Option<std::path::PathBuf>,
+    /// Largest payload to decompress, in bytes
+    #[arg(long = "max-size", default_value_t = pngme::payload::DEFAULT_MAX_SIZE)]
+    pub max_size: u64,
 }
 
-/// pngme remove -f ./file.png
+/// pngme remove -f ./file.png (-c ruSt [--name notes | --all] | --trailer) [-i | -o out.png]
 #[derive(Debug, Args)]
 pub struct RemoveArgs {
+    /// PNG file to read, `-` for stdin
     #[arg(short = 'f', long = "file")]
     pub file_to_clean: std::path::PathBuf,
-    #[arg(short = 'c', long = "type_chunk")]
-    pub type_chunk: String,
+    #[arg(short = 'c', long = "type_chunk", required_unless_present = "trailer")]
+    pub type_chunk: Option<String>,
+    /// Remove the data after IEND instead of a chunk
+    #[arg(long = "trailer", conflicts_with_all = ["type_chunk", "name", "all"])]
+    pub trailer: bool,
+    /// Remove the messages with this name instead of the first message
+    #[arg(long = "name", conflicts_with = "all")]
+    pub name: Option<String>,
+    /// Remove every chunk of this type
+    #[arg(long = "all")]
+    pub all: bool,
+    /// File to write, `-` for stdout
+    #[arg(short = 'o', long = "output", conflicts_with = "in_place")]
+    pub output: Option<std::path::PathBuf>,
+    /// Replace the file instead of writing a new one next to it
+    #[arg(short = 'i', long = "in-place")]
+    pub in_place: bool,
+    /// Keep the modification time of the file when editing it in place
+    #[arg(long = "preserve-mtime", requires = "in_place")]
+    pub preserve_mtime: bool,
 }
 
 /// pngme print --file ./file.png
 #[derive(Debug, Args)]
 pub struct PrintArgs {
+    /// PNG file to read, `-` for stdin
     #[arg(short = 'f', long = "file")]
     pub file_to_print: std::path::PathBuf,
 }
+
+/// pngme check -f ./file.png
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// PNG file to read, `-` for stdin
+    #[arg(short = 'f', long = "file")]
+    pub file_to_check: std::path::PathBuf,
+}
+
+/// pngme keygen [--signing] [-o key.txt]
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// File to write the secret key to, stdout if not given
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<std::path::PathBuf>,
+    /// Generate an ed25519 key for sign, instead of an X25519 key for --recipient
+    #[arg(long = "signing")]
+    pub signing: bool,
+}
+
+/// pngme sign -f ./file.png --key ed25519.key [--include ruSt] [-i | -o out.png]
+#[derive(Debug, Args)]
+pub struct SignArgs {
+    /// PNG file to read, `-` for stdin
+    #[arg(short = 'f', long = "file")]
+    pub file_to_sign: std::path::PathBuf,
+    /// File with the ed25519 secret key, written by keygen --signing
+    #[arg(short = 'k', long = "key")]
+    pub key: std::path::PathBuf,
+    /// Ancillary chunk type to sign besides the critical chunks, may be repeated
+    #[arg(long = "include")]
+    pub include: Vec<String>,
+    /// File to write, `-` for stdout
+    #[arg(short = 'o', long = "output", conflicts_with = "in_place")]
+    pub output: Option<std::path::PathBuf>,
+    /// Replace the file instead of writing a new one next to it
+    #[arg(short = 'i', long = "in-place")]
+    pub in_place: bool,
+    /// Keep the modification time of the file when editing it in place
+    #[arg(long = "preserve-mtime", requires = "in_place")]
+    pub preserve_mtime: bool,
+}
+
+/// pngme verify -f ./file.png --pubkey <key>
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// PNG file to read, `-` for stdin
+    #[arg(short = 'f', long = "file")]
+    pub file_to_verify: std::path::PathBuf,
+    /// Public key of the signer, as printed by keygen --signing
+    #[arg(short = 'p', long = "pubkey")]
+    pub pubkey: String,
+}
+
+/// pngme list-messages -f ./file.png [-c ruSt]
+#[derive(Debug, Args)]
+pub struct ListMessagesArgs {
+    /// PNG file to read, `-` for stdin
+    #[arg(short = 'f', long = "file")]
+    pub file: std::path::PathBuf,
+    /// Only list the messages of this chunk type, instead of every private ancillary chunk
+    #[arg(short = 'c', long = "type_chunk")]
+    pub type_chunk: Option<String>,
+}
+
+/// pngme capacity -f ./file.png [--encrypt | --recipients 2] [--payload-file notes.txt]
+#[derive(Debug, Args)]
+pub struct CapacityArgs {
+    /// PNG file to read, `-` for stdin
+    #[arg(short = 'f', long = "file")]
+    pub file: std::path::PathBuf,
+    /// Count the overhead of the encryption with a passphrase
+    #[arg(long = "encrypt", conflicts_with = "recipients")]
+    pub encrypt: bool,
+    /// Count the overhead of the encryption for this number of recipients
+    #[arg(long = "recipients")]
+    pub recipients: Option<usize>,
+    /// Count the header of this file, and tell which methods can hide it
+    #[arg(long = "payload-file")]
+    pub payload_file: Option<std::path::PathBuf>,
+}
+
+/// pngme scan -f ./file.png [-f ./other.png ...]
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// PNG file to scan, `-` for stdin, may be repeated
+    #[arg(short = 'f', long = "file", required = true)]
+    pub files: Vec<std::path::PathBuf>,
+}
+
+/// Bits per sample of --lsb-bits, between 1 and `lsb::MAX_BITS`
+fn lsb_bits(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(bits) if (1..=pngme::lsb::MAX_BITS).contains(&bits) => Ok(bits),
+        _ => Err(format!("must be between 1 and {}", pngme::lsb::MAX_BITS)),
+    }
+}
@@ -0,0 +1,580 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::reader::read_up_to;
+use crate::Result;
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Chunks which may appear only once in a PNG
+const UNIQUE_CHUNKS: [&[u8; 4]; 13] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"bKGD", b"hIST",
+    b"tRNS", b"pHYs", b"tIME",
+];
+
+const KNOWN_CRITICAL_CHUNKS: [&[u8; 4]; 4] = [b"IHDR", b"PLTE", b"IDAT", b"IEND"];
+
+/// How bad a finding is, ordered from the least to the most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "WARNING"),
+            Severity::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// One violation of the PNG spec found by [`check`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    severity: Severity,
+    offset: usize,
+    code: &'static str,
+    message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, offset: usize, code: &'static str, message: String) -> Finding {
+        Finding {
+            severity,
+            offset,
+            code,
+            message,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Byte offset, from the start of the file, of the chunk or data at fault
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Short identifier of the kind of violation, e.g. `crc-mismatch`
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<7} offset {:>8} : {}",
+            self.severity.to_string(),
+            self.offset,
+            self.message
+        )
+    }
+}
+
+/// Chunk seen while scanning, kept for the checks on the chunks order
+struct ScannedChunk {
+    offset: usize,
+    chunk_type: [u8; 4],
+}
+
+impl ScannedChunk {
+    fn is(&self, chunk_type: &[u8; 4]) -> bool {
+        &self.chunk_type == chunk_type
+    }
+
+    fn name(&self) -> String {
+        String::from_utf8_lossy(&self.chunk_type).to_string()
+    }
+}
+
+/// Feeds the CRC with the bytes written to it
+struct CrcWriter<'a> {
+    digest: Digest<'a, u32>,
+}
+
+impl Write for CrcWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.digest.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Walk a PNG like pngcheck and report every violation of the spec found :
+/// bad signature, truncated or invalid chunks, CRC errors, missing or
+/// misplaced IHDR, PLTE, IDAT and IEND, duplicated unique chunks, reserved bit
+/// set, and chunks or other data after IEND.
+///
+/// Unlike [`Png::try_from`], the scan goes on after a CRC error or an invalid
+/// chunk type, and chunk data is not kept in memory. The findings are sorted
+/// by offset. Only I/O errors of the reader are returned as `Err`.
+pub fn check<R: Read>(mut reader: R) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut signature = [0u8; 8];
+    let read = read_up_to(&mut reader, &mut signature)?;
+    if read < signature.len() || signature != Png::STANDARD_HEADER {
+        findings.push(Finding::new(
+            Severity::Error,
+            0,
+            "bad-signature",
+            "no valid PNG signature".to_string(),
+        ));
+        return Ok(findings);
+    }
+
+    let mut chunks: Vec<ScannedChunk> = Vec::new();
+    let mut ihdr_data: Option<(usize, Vec<u8>)> = None;
+    let mut offset = Png::STANDARD_HEADER.len();
+    loop {
+        let mut header = [0u8; 8];
+        let read = read_up_to(&mut reader, &mut header)?;
+        if read == 0 {
+            break;
+        }
+        if read < header.len() {
+            findings.push(truncated(offset));
+            break;
+        }
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let type_bytes = [header[4], header[5], header[6], header[7]];
+        if length > Chunk::MAX_LENGTH {
+            findings.push(Finding::new(
+                Severity::Error,
+                offset,
+                "chunk-too-long",
+                format!("chunk length {} above the 2^31-1 limit", length),
+            ));
+            break;
+        }
+        match ChunkType::try_from(type_bytes) {
+            Ok(chunk_type) if !chunk_type.is_reserved_bit_valid() => {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    offset,
+                    "reserved-bit",
+                    format!("chunk {} has the reserved bit set", chunk_type),
+                ))
+            }
+            Ok(_) => (),
+            Err(_) => findings.push(Finding::new(
+                Severity::Error,
+                offset,
+                "invalid-chunk-type",
+                format!("invalid chunk type bytes {:?}", type_bytes),
+            )),
+        }
+
+        let mut crc = CrcWriter {
+            digest: CRC.digest(),
+        };
+        crc.digest.update(&type_bytes);
+        let mut data = (&mut reader).take(length as u64);
+        let copied = if &type_bytes == b"IHDR" && length as usize == Ihdr::LENGTH {
+            let mut bytes = Vec::new();
+            data.read_to_end(&mut bytes)?;
+            crc.digest.update(&bytes);
+            let copied = bytes.len() as u64;
+            ihdr_data.get_or_insert((offset, bytes));
+            copied
+        } else {
+            io::copy(&mut data, &mut crc)?
+        };
+        let mut crc_bytes = [0u8; 4];
+        if copied < length as u64 || read_up_to(&mut reader, &mut crc_bytes)? < crc_bytes.len() {
+            findings.push(truncated(offset));
+            break;
+        }
+        let expected = crc.digest.finalize();
+        let actual = u32::from_be_bytes(crc_bytes);
+        if expected != actual {
+            findings.push(Finding::new(
+                Severity::Error,
+                offset,
+                "crc-mismatch",
+                format!(
+                    "CRC error in chunk {} : expected {:#010x}, found {:#010x}",
+                    String::from_utf8_lossy(&type_bytes),
+                    expected,
+                    actual
+                ),
+            ));
+        }
+
+        chunks.push(ScannedChunk {
+            offset,
+            chunk_type: type_bytes,
+        });
+        offset += length as usize + Chunk::SIZE_WITHOUT_DATA;
+
+        if &type_bytes == b"IEND" {
+            check_trailer(&mut reader, offset, &mut findings)?;
+            break;
+        }
+    }
+
+    check_structure(&chunks, ihdr_data, offset, &mut findings);
+    findings.sort_by_key(|f| f.offset);
+    Ok(findings)
+}
+
+/// Report what follows IEND : each complete chunk with its type, then the
+/// bytes left which don't start with a valid chunk header
+fn check_trailer<R: Read>(
+    reader: &mut R,
+    mut offset: usize,
+    findings: &mut Vec<Finding>,
+) -> Result<()> {
+    loop {
+        let mut header = [0u8; 8];
+        let read = read_up_to(reader, &mut header)?;
+        if read == 0 {
+            return Ok(());
+        }
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let mut body = 0;
+        let chunk_type = match ChunkType::try_from([header[4], header[5], header[6], header[7]]) {
+            Ok(t) if read == header.len() && length <= Chunk::MAX_LENGTH => Some(t),
+            _ => None,
+        };
+        if let Some(chunk_type) = chunk_type {
+            let body_length = length as u64 + 4;
+            body = io::copy(&mut reader.take(body_length), &mut io::sink())?;
+            if body == body_length {
+                findings.push(Finding::new(
+                    Severity::Warning,
+                    offset,
+                    "chunk-after-iend",
+                    format!("chunk {} of {} bytes after IEND", chunk_type, length),
+                ));
+                offset += length as usize + Chunk::SIZE_WITHOUT_DATA;
+                continue;
+            }
+        }
+        let trailing = read as u64 + body + io::copy(reader, &mut io::sink())?;
+        findings.push(Finding::new(
+            Severity::Warning,
+            offset,
+            "trailing-data",
+            format!("{} bytes of data after IEND", trailing),
+        ));
+        return Ok(());
+    }
+}
+
+/// Checks on the order and number of the chunks
+fn check_structure(
+    chunks: &[ScannedChunk],
+    ihdr_data: Option<(usize, Vec<u8>)>,
+    end: usize,
+    findings: &mut Vec<Finding>,
+) {
+    let first_offset = Png::STANDARD_HEADER.len();
+    match chunks.iter().position(|c| c.is(b"IHDR")) {
+        None => findings.push(Finding::new(
+            Severity::Error,
+            first_offset,
+            "missing-ihdr",
+            "no IHDR chunk".to_string(),
+        )),
+        Some(0) => (),
+        Some(i) => findings.push(Finding::new(
+            Severity::Error,
+            chunks[i].offset,
+            "misplaced-ihdr",
+            "IHDR is not the first chunk".to_string(),
+        )),
+    }
+    let ihdr = match ihdr_data {
+        Some((offset, data)) => match Ihdr::try_from(data.as_slice()) {
+            Ok(ihdr) => Some(ihdr),
+            Err(e) => {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    offset,
+                    "invalid-ihdr",
+                    e.to_string(),
+                ));
+                None
+            }
+        },
+        None => {
+            if let Some(c) = chunks.iter().find(|c| c.is(b"IHDR")) {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    c.offset,
+                    "invalid-ihdr",
+                    format!("IHDR data is not {} bytes long", Ihdr::LENGTH),
+                ));
+            }
+            None
+        }
+    };
+    if !chunks.last().is_some_and(|c| c.is(b"IEND")) {
+        findings.push(Finding::new(
+            Severity::Error,
+            end,
+            "missing-iend",
+            "no IEND chunk at the end of the file".to_string(),
+        ));
+    }
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        if UNIQUE_CHUNKS.contains(&&chunk.chunk_type)
+            && chunks[..i].iter().any(|c| c.chunk_type == chunk.chunk_type)
+        {
+            let severity = if chunk.chunk_type[0].is_ascii_uppercase() {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            findings.push(Finding::new(
+                severity,
+                chunk.offset,
+                "duplicate-chunk",
+                format!("chunk {} must not appear more than once", chunk.name()),
+            ));
+        }
+        if chunk.chunk_type[0].is_ascii_uppercase()
+            && !KNOWN_CRITICAL_CHUNKS.contains(&&chunk.chunk_type)
+        {
+            findings.push(Finding::new(
+                Severity::Error,
+                chunk.offset,
+                "unknown-critical-chunk",
+                format!("unknown critical chunk {}", chunk.name()),
+            ));
+        }
+    }
+
+    let first_idat = chunks.iter().position(|c| c.is(b"IDAT"));
+    match first_idat {
+        None => findings.push(Finding::new(
+            Severity::Error,
+            end,
+            "missing-idat",
+            "no IDAT chunk".to_string(),
+        )),
+        Some(first) => {
+            let last = chunks.iter().rposition(|c| c.is(b"IDAT")).unwrap_or(first);
+            if let Some(c) = chunks[first..=last].iter().find(|c| !c.is(b"IDAT")) {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    c.offset,
+                    "non-consecutive-idat",
+                    format!("chunk {} between IDAT chunks", c.name()),
+                ));
+            }
+        }
+    }
+
+    let plte = chunks.iter().position(|c| c.is(b"PLTE"));
+    if let (Some(plte), Some(first)) = (plte, first_idat) {
+        if plte > first {
+            findings.push(Finding::new(
+                Severity::Error,
+                chunks[plte].offset,
+                "misplaced-plte",
+                "PLTE after IDAT".to_string(),
+            ));
+        }
+    }
+    if let Some(ihdr) = ihdr {
+        match (ihdr.color_type(), plte) {
+            (ColorType::Indexed, None) => findings.push(Finding::new(
+                Severity::Error,
+                end,
+                "missing-plte",
+                "indexed colour image without PLTE".to_string(),
+            )),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(i)) => {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    chunks[i].offset,
+                    "unexpected-plte",
+                    "PLTE in a grayscale image".to_string(),
+                ))
+            }
+            _ => (),
+        }
+    }
+}
+
+fn truncated(offset: usize) -> Finding {
+    Finding::new(
+        Severity::Error,
+        offset,
+        "truncated-chunk",
+        "truncated chunk".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr() -> Chunk {
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0])
+    }
+
+    fn bytes(chunks: Vec<Chunk>) -> Vec<u8> {
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    fn codes(bytes: &[u8]) -> Vec<&'static str> {
+        check(bytes).unwrap().iter().map(|f| f.code()).collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        let png = bytes(vec![ihdr(), chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        assert!(check(png.as_slice()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        assert_eq!(codes(b"not a png"), ["bad-signature"]);
+    }
+
+    #[test]
+    fn test_missing_ihdr_and_iend() {
+        let png = bytes(vec![chunk("IDAT", b"x")]);
+        assert_eq!(codes(&png), ["missing-ihdr", "missing-iend"]);
+    }
+
+    #[test]
+    fn test_misplaced_and_duplicate_chunks() {
+        let png = bytes(vec![
+            chunk("IDAT", b"x"),
+            ihdr(),
+            chunk("tEXt", b"a"),
+            chunk("IDAT", b"y"),
+            chunk("PLTE", b"abc"),
+            chunk("gAMA", b"1"),
+            chunk("gAMA", b"2"),
+            chunk("IEND", b""),
+        ]);
+        let findings = check(png.as_slice()).unwrap();
+        let codes: Vec<&str> = findings.iter().map(|f| f.code()).collect();
+        assert_eq!(
+            codes,
+            [
+                "misplaced-ihdr",
+                "non-consecutive-idat",
+                "misplaced-plte",
+                "duplicate-chunk"
+            ]
+        );
+        assert_eq!(findings[0].offset(), 8 + 13);
+        assert_eq!(findings[3].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_crc_and_type_errors_do_not_stop_the_scan() {
+        let mut png = bytes(vec![
+            ihdr(),
+            chunk("IDAT", b"x"),
+            chunk("ruSt", b"z"),
+            chunk("IEND", b""),
+        ]);
+        // CRC of IDAT, then type of ruSt
+        let idat_crc = 8 + 25 + 12;
+        png[idat_crc] ^= 0xff;
+        png[idat_crc + 4 + 4] = b'1';
+        let findings = check(png.as_slice()).unwrap();
+        let codes: Vec<&str> = findings.iter().map(|f| f.code()).collect();
+        assert_eq!(codes, ["crc-mismatch", "invalid-chunk-type", "crc-mismatch"]);
+        assert_eq!(findings[0].offset(), 33);
+        assert_eq!(findings[1].offset(), 46);
+    }
+
+    #[test]
+    fn test_reserved_bit() {
+        let png = bytes(vec![
+            ihdr(),
+            chunk("IDAT", b"x"),
+            chunk("rust", b"z"),
+            chunk("IEND", b""),
+        ]);
+        assert_eq!(codes(&png), ["reserved-bit"]);
+    }
+
+    #[test]
+    fn test_trailing_data_and_truncation() {
+        let mut png = bytes(vec![ihdr(), chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        let len = png.len();
+        png.extend_from_slice(b"hidden");
+        let findings = check(png.as_slice()).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].code(), "trailing-data");
+        assert_eq!(findings[0].offset(), len);
+
+        assert_eq!(codes(&png[..len - 2]), ["truncated-chunk", "missing-iend"]);
+    }
+
+    #[test]
+    fn test_chunks_after_iend() {
+        let mut png = bytes(vec![ihdr(), chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        let len = png.len();
+        chunk("ruSt", b"hidden").write_to(&mut png).unwrap();
+        chunk("IDAT", b"more").write_to(&mut png).unwrap();
+        let findings = check(png.as_slice()).unwrap();
+        let codes: Vec<&str> = findings.iter().map(|f| f.code()).collect();
+        assert_eq!(codes, ["chunk-after-iend", "chunk-after-iend"]);
+        assert_eq!(findings[0].offset(), len);
+        assert_eq!(findings[1].offset(), len + 18);
+        assert!(findings[0].message().contains("ruSt"));
+
+        // a truncated chunk is only data
+        let end = png.len();
+        png.extend_from_slice(&[0, 0, 0, 9, b't', b'E', b'X', b't', 1]);
+        let findings = check(png.as_slice()).unwrap();
+        assert_eq!(findings.len(), 3);
+        assert_eq!(findings[2].code(), "trailing-data");
+        assert_eq!(findings[2].offset(), end);
+        assert!(findings[2].message().starts_with("9 bytes"));
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let indexed = chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0]);
+        let png = bytes(vec![indexed, chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        assert_eq!(codes(&png), ["missing-plte"]);
+
+        let gray = chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+        let png = bytes(vec![
+            gray,
+            chunk("PLTE", b"abc"),
+            chunk("IDAT", b"x"),
+            chunk("IEND", b""),
+        ]);
+        assert_eq!(codes(&png), ["unexpected-plte"]);
+    }
+
+    #[test]
+    fn test_severity_order() {
+        assert!(Severity::Error > Severity::Warning);
+    }
+}
@@ -1,22 +1,54 @@
 use crate::chunk_type::ChunkType;
-use crate::Result;
-use anyhow::bail;
+use crate::{PngError, Result};
 use crc::{Crc, CRC_32_ISO_HDLC};
+use serde::Serialize;
 use std::fmt::Display;
+use std::io::Write;
 
-#[derive(Debug, PartialEq, Eq)]
+/// A PNG chunk : length, type, data and CRC.
+/// See http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
+///
+/// Serialized without its data, like a [`ChunkSummary`](crate::ChunkSummary).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct Chunk {
+    #[serde(rename = "length")]
     chunk_length: u32,
     chunk_type: ChunkType,
+    #[serde(skip)]
     chunk_data: Vec<u8>,
+    #[serde(rename = "crc")]
     chunk_crc: u32,
 }
 
 impl Chunk {
+    /// Number of bytes of a chunk besides its data (length, type and CRC)
     pub const SIZE_WITHOUT_DATA: usize = 12;
-
+    /// Maximum length of the data allowed by the spec : 2^31-1 bytes
+    pub const MAX_LENGTH: u32 = i32::MAX as u32;
+
+    /// Build a chunk from its type and data, the length and CRC are computed.
+    ///
+    /// # Panics
+    ///
+    /// If the data is longer than [`MAX_LENGTH`](Chunk::MAX_LENGTH), use
+    /// [`try_new`](Chunk::try_new) for data of any size.
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let chunk_length: u32 = data.len().try_into().unwrap();
+        Self::try_new(chunk_type, data).expect("chunk data longer than 2^31-1 bytes")
+    }
+
+    /// Build a chunk like [`new`](Chunk::new). Returns [`PngError::ChunkTooLong`]
+    /// if the data is longer than [`MAX_LENGTH`](Chunk::MAX_LENGTH) : it
+    /// couldn't be parsed back.
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        let chunk_length = match u32::try_from(data.len()) {
+            Ok(length) if length <= Self::MAX_LENGTH => length,
+            length => {
+                return Err(PngError::ChunkTooLong {
+                    offset: 0,
+                    length: length.unwrap_or(u32::MAX),
+                })
+            }
+        };
         let chunk_crc = Self::calculate_crc(chunk_type.bytes().as_ref(), &data);
 
         let chunk: Chunk = Chunk {
@@ -25,11 +57,11 @@ impl Chunk {
             chunk_data: data,
             chunk_crc,
         };
-        chunk
+        Ok(chunk)
     }
 
-    pub fn _length(&self) -> u32 {
-        dbg!(self.chunk_length);
+    /// Length of the data, in bytes
+    pub fn length(&self) -> u32 {
         self.chunk_length
     }
 
@@ -37,52 +69,79 @@ impl Chunk {
         &self.chunk_type
     }
 
-    pub fn _data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         let data: &[u8] = self.chunk_data.as_slice();
         data
     }
 
-    pub fn _crc(&self) -> u32 {
+    pub fn crc(&self) -> u32 {
         self.chunk_crc
     }
 
+    /// Data of the chunk interpreted as an UTF-8 string
     pub fn data_as_string(&self) -> Result<String> {
         let s = String::from_utf8(self.chunk_data.to_vec())?;
         Ok(s)
     }
 
+    /// Serialize the chunk as it is stored in a PNG file
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = Vec::new();
-        bytes.append(&mut self.chunk_length.to_be_bytes().to_vec());
-        bytes.append(&mut self.chunk_type.bytes().to_vec());
-        bytes.append(&mut self.chunk_data.clone());
-        bytes.append(&mut self.chunk_crc.to_be_bytes().to_vec());
+        let mut bytes: Vec<u8> =
+            Vec::with_capacity(self.chunk_data.len() + Self::SIZE_WITHOUT_DATA);
+        self.write_to(&mut bytes).expect("writing to a Vec can't fail");
         bytes
     }
 
+    /// Write the chunk as it is stored in a PNG file, without copying its data
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.chunk_length.to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.chunk_data)?;
+        writer.write_all(&self.chunk_crc.to_be_bytes())
+    }
+
+    /// CRC-32 computed over the chunk type and the chunk data
     pub fn calculate_crc(chunk_type: &[u8], chunk_data: &[u8]) -> u32 {
-        let mut crc_input = Vec::new();
-        crc_input.append(&mut chunk_type.to_owned());
-        crc_input.append(&mut chunk_data.to_owned());
         let crc: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        crc.checksum(&crc_input)
+        let mut digest = crc.digest();
+        digest.update(chunk_type);
+        digest.update(chunk_data);
+        digest.finalize()
     }
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = crate::Error;
+    type Error = PngError;
 
     /// construit à partir d'un tableau d'octets un chunk. Le tableau doit être complet :
     /// octets de longueur, octets du type, octets data et enfin octets CRC
     /// le CRC est recalculé pour s'assurer de la consistance du chunk
+    /// Offsets in the returned errors are relative to the start of the array
     fn try_from(array: &[u8]) -> std::result::Result<Self, Self::Error> {
-        let chunk_length = u32::from_be_bytes([array[0], array[1], array[2], array[3]]);
-        let chunk_type = ChunkType::try_from([array[4], array[5], array[6], array[7]])?;
-        // let chunk_type = match r {
-        //     Ok(ct) => ct,
-        //     Err(_) => bail!("Invalid ChunkType"),
-        // };
         let array_length = array.len();
+        if array_length < Chunk::SIZE_WITHOUT_DATA {
+            return Err(PngError::TruncatedChunk { offset: 0 });
+        }
+        let chunk_length = u32::from_be_bytes([array[0], array[1], array[2], array[3]]);
+        if chunk_length > Chunk::MAX_LENGTH {
+            return Err(PngError::ChunkTooLong {
+                offset: 0,
+                length: chunk_length,
+            });
+        }
+        let data_length = array_length - Chunk::SIZE_WITHOUT_DATA;
+        if data_length < chunk_length as usize {
+            return Err(PngError::TruncatedChunk { offset: 0 });
+        }
+        if data_length > chunk_length as usize {
+            return Err(PngError::LengthMismatch {
+                offset: 0,
+                declared: chunk_length,
+                actual: data_length,
+            });
+        }
+        let chunk_type = ChunkType::try_from([array[4], array[5], array[6], array[7]])
+            .map_err(|e| e.at_offset(4))?;
         let data: &[u8] = &array[8..array_length - 4];
         let v_data = data.to_vec();
         let chunk_crc = u32::from_be_bytes([
@@ -93,7 +152,12 @@ impl TryFrom<&[u8]> for Chunk {
         ]);
         let expected_crc = Chunk::calculate_crc(chunk_type.bytes().as_ref(), data);
         if expected_crc != chunk_crc {
-            bail!("CRC error");
+            return Err(PngError::CrcMismatch {
+                chunk_type: chunk_type.to_string(),
+                offset: 0,
+                expected: expected_crc,
+                actual: chunk_crc,
+            });
         }
 
         let chunk = Chunk {
@@ -150,14 +214,23 @@ mod tests {
             .as_bytes()
             .to_vec();
         let chunk = Chunk::new(chunk_type, data);
-        assert_eq!(chunk._length(), 42);
-        assert_eq!(chunk._crc(), 2882656334);
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_try_new_chunk() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let chunk = Chunk::try_new(chunk_type.clone(), b"data".to_vec()).unwrap();
+        assert_eq!(chunk, Chunk::new(chunk_type, b"data".to_vec()));
+        let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed, chunk);
     }
 
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
-        assert_eq!(chunk._length(), 42);
+        assert_eq!(chunk.length(), 42);
     }
 
     #[test]
@@ -177,7 +250,7 @@ mod tests {
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
-        assert_eq!(chunk._crc(), 2882656334);
+        assert_eq!(chunk.crc(), 2882656334);
     }
 
     #[test]
@@ -201,10 +274,10 @@ mod tests {
         let chunk_string = chunk.data_as_string().unwrap();
         let expected_chunk_string = String::from("This is where your secret message will be!");
 
-        assert_eq!(chunk._length(), 42);
+        assert_eq!(chunk.length(), 42);
         assert_eq!(chunk.chunk_type().to_string(), String::from("RuSt"));
         assert_eq!(chunk_string, expected_chunk_string);
-        assert_eq!(chunk._crc(), 2882656334);
+        assert_eq!(chunk.crc(), 2882656334);
     }
 
     #[test]
@@ -225,7 +298,14 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(PngError::CrcMismatch {
+                expected: 2882656334,
+                actual: 2882656333,
+                ..
+            })
+        ));
     }
 
     #[test]
@@ -248,4 +328,49 @@ mod tests {
 
         let _chunk_string = format!("{}", chunk);
     }
+
+    #[test]
+    fn test_chunk_serialize() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![1, 2]);
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["length"], 2);
+        assert_eq!(json["chunk_type"], "RuSt");
+        assert!(json.get("data").is_none());
+        assert_eq!(json["crc"], chunk.crc());
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_data = testing_chunk().as_bytes();
+
+        for len in [0, 3, 11, chunk_data.len() - 1] {
+            let chunk = Chunk::try_from(&chunk_data[..len]);
+            assert!(matches!(chunk, Err(PngError::TruncatedChunk { offset: 0 })));
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_above_limit() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[0..4].copy_from_slice(&(Chunk::MAX_LENGTH + 1).to_be_bytes());
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert!(matches!(chunk, Err(PngError::ChunkTooLong { offset: 0, .. })));
+    }
+
+    #[test]
+    fn test_chunk_length_mismatch() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[0..4].copy_from_slice(&10u32.to_be_bytes());
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert!(matches!(
+            chunk,
+            Err(PngError::LengthMismatch {
+                declared: 10,
+                actual: 42,
+                ..
+            })
+        ));
+    }
 }
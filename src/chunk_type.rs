@@ -1,7 +1,9 @@
-use anyhow::bail;
+use crate::PngError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::Display;
 
-#[derive(Debug, PartialEq, Eq)]
+/// The 4 bytes type code of a chunk, e.g. `IHDR` or `ruSt`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
     type_str: String,
 }
@@ -14,14 +16,13 @@ impl ChunkType {
         for (cpt,b) in self.type_str.as_bytes().iter().enumerate() {
             bytes[cpt] = *b;
         }
-        //dbg!(&bytes);
         bytes
     }
 
     /// Valid if reserved bit is valid and all bytes are represented
     /// by A-Z or a-z characters
-    pub fn _is_valid(&self) -> bool {
-        if self._is_reserved_bit_valid() {
+    pub fn is_valid(&self) -> bool {
+        if self.is_reserved_bit_valid() {
             for byte in self.bytes() {
                 if !byte.is_ascii_lowercase() && !byte.is_ascii_uppercase() {
                     return false;
@@ -39,7 +40,7 @@ impl ChunkType {
     /// # Examples
     /// ChunkType::from_str("RuSt").unwrap();
     ///  assert_eq!(expected, actual);
-    pub fn _is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         let byte_to_check = self.bytes()[0];
         if byte_to_check & 0b00100000 == 32 {
             return false;
@@ -49,7 +50,7 @@ impl ChunkType {
 
     /// Spec : Private bit: bit 5 of second byte
     /// 0 (uppercase) = public, 1 (lowercase) = private.
-    pub fn _is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         let byte_to_check = self.bytes()[1];
         if byte_to_check & 0b00100000 == 32 {
             return false;
@@ -59,7 +60,7 @@ impl ChunkType {
 
     /// Reserved bit: bit 5 of third byte
     /// Must be 0 (uppercase) in files conforming to this version of PNG.
-    pub fn _is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         let byte_to_check = self.bytes()[2];
         if byte_to_check & 0b00100000 == 32 {
             return false;
@@ -69,7 +70,7 @@ impl ChunkType {
 
     ///Safe-to-copy bit: bit 5 of fourth byte
     /// 0 (uppercase) = unsafe to copy, 1 (lowercase) = safe to copy.
-    pub fn _is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         let byte_to_check = self.bytes()[3];
         if byte_to_check & 0b00100000 == 0 {
             return false;
@@ -79,12 +80,15 @@ impl ChunkType {
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = crate::Error;
+    type Error = PngError;
 
     fn try_from(array: [u8; 4]) -> Result<Self, Self::Error> {
         for b in array {
-            if !b.is_ascii() {
-                bail!("Input not in A-Z or a-z");
+            if !b.is_ascii_lowercase() && !b.is_ascii_uppercase() {
+                return Err(PngError::InvalidChunkType {
+                    bytes: array.to_vec(),
+                    offset: 0,
+                });
             }
         }
         let v = array.to_vec();
@@ -95,13 +99,18 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl std::str::FromStr for ChunkType {
-    type Err = crate::Error;
+    type Err = PngError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        for b in s.as_bytes() {
-            if !b.is_ascii_lowercase() && !b.is_ascii_uppercase() {
-                bail!("Input not in A-Z or a-z");
-            }
+        let invalid = s.len() != 4
+            || s
+                .bytes()
+                .any(|b| !b.is_ascii_lowercase() && !b.is_ascii_uppercase());
+        if invalid {
+            return Err(PngError::InvalidChunkType {
+                bytes: s.as_bytes().to_vec(),
+                offset: 0,
+            });
         }
         let ct: ChunkType = ChunkType {
             type_str: s.to_string(),
@@ -110,6 +119,20 @@ impl std::str::FromStr for ChunkType {
     }
 }
 
+/// Serialized as its 4 letters string
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.type_str)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl Display for ChunkType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.type_str)
@@ -140,72 +163,87 @@ mod tests {
     #[test]
     pub fn test_chunk_type_is_critical() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
-        assert!(chunk._is_critical());
+        assert!(chunk.is_critical());
     }
 
     #[test]
     pub fn test_chunk_type_is_not_critical() {
         let chunk = ChunkType::from_str("ruSt").unwrap();
-        assert!(!chunk._is_critical());
+        assert!(!chunk.is_critical());
     }
 
     #[test]
     pub fn test_chunk_type_is_public() {
         let chunk = ChunkType::from_str("RUSt").unwrap();
-        assert!(chunk._is_public());
+        assert!(chunk.is_public());
     }
 
     #[test]
     pub fn test_chunk_type_is_not_public() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
-        assert!(!chunk._is_public());
+        assert!(!chunk.is_public());
     }
 
     #[test]
     pub fn test_chunk_type_is_reserved_bit_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
-        assert!(chunk._is_reserved_bit_valid());
+        assert!(chunk.is_reserved_bit_valid());
     }
 
     #[test]
     pub fn test_chunk_type_is_reserved_bit_invalid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
-        assert!(!chunk._is_reserved_bit_valid());
+        assert!(!chunk.is_reserved_bit_valid());
     }
 
     #[test]
     pub fn test_chunk_type_is_safe_to_copy() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
-        assert!(chunk._is_safe_to_copy());
+        assert!(chunk.is_safe_to_copy());
     }
 
     #[test]
     pub fn test_chunk_type_is_unsafe_to_copy() {
         let chunk = ChunkType::from_str("RuST").unwrap();
-        assert!(!chunk._is_safe_to_copy());
+        assert!(!chunk.is_safe_to_copy());
     }
 
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
-        assert!(chunk._is_valid());
+        assert!(chunk.is_valid());
     }
 
     #[test]
     pub fn test_invalid_chunk_is_valid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
-        assert!(!chunk._is_valid());
+        assert!(!chunk.is_valid());
 
         let chunk = ChunkType::from_str("Ru1t");
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_invalid_length() {
+        let chunk = ChunkType::from_str("RuStY");
+        assert!(matches!(chunk, Err(PngError::InvalidChunkType { .. })));
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_serde() {
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert_eq!(json, "\"RuSt\"");
+        assert_eq!(serde_json::from_str::<ChunkType>(&json).unwrap(), chunk);
+        assert!(serde_json::from_str::<ChunkType>("\"Ru1t\"").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
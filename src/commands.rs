@@ -1,72 +1,804 @@
-use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Error, Result};
+use crate::args::Format;
+use crate::output;
+use anyhow::{bail, Result};
+use pngme::crypto::{self, Identity, Recipient, SealMethod};
+use pngme::payload::{self, Compression, Payload};
+use pngme::signature::{self, Signed, SigningKey, VerifyingKey};
+use pngme::lsb;
+use pngme::messages::{self, MessageInfo};
+use pngme::scan::Verdict;
+use pngme::slack;
+use pngme::split;
+use pngme::trailer;
+use pngme::{
+    edit, edit_file, edit_to_file, Chunk, ChunkType, ColorType, EditOp, Ihdr, Png, PngError,
+    PngReader, Severity,
+};
 use chrono::{DateTime, Local};
-use log::{info, warn};
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::json;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+/// How encode encrypts the message
+pub enum Seal {
+    Plain,
+    Passphrase(String),
+    Recipients(Vec<Recipient>),
+}
+
+/// How decode decrypts the message
+pub enum Unseal {
+    Plain,
+    Passphrase(String),
+    Identity(Identity),
+}
+
+/// Where encode, remove and sign write the edited PNG
+pub struct Destination<'a> {
+    /// File to write, `-` for stdout
+    pub output: Option<&'a Path>,
+    /// Replace the input file
+    pub in_place: bool,
+    /// Keep the modification time of the input file when replacing it
+    pub preserve_mtime: bool,
+}
+
+/// Where encode hides the payload
+pub enum Placement<'a> {
+    /// In chunks of this type
+    Chunk {
+        chunk_type: String,
+        /// Name of the message, repeated in each chunk
+        name: Option<&'a str>,
+        /// Largest chunk to write before the payload is split
+        max_chunk_size: usize,
+    },
+    /// In the `bits` lowest bits of the pixels, in an order drawn from `key`
+    /// if given
+    Lsb { key: Option<&'a str>, bits: u8 },
+    /// After the `IEND` chunk
+    Trailer,
+    /// In the `IDAT` chunks, after the zlib stream
+    IdatSlack,
+}
+
+/// Where decode looks for the payload
+pub enum Source<'a> {
+    /// In the chunks of this type, the message called `name` if given
+    Chunk {
+        chunk_type: String,
+        name: Option<&'a str>,
+    },
+    /// In the lowest bits of the pixels
+    Lsb { key: Option<&'a str>, bits: u8 },
+    /// After the `IEND` chunk
+    Trailer,
+    /// In the `IDAT` chunks, after the zlib stream
+    IdatSlack,
+}
+
+/// Path standing for stdin or stdout
+const STDIO: &str = "-";
+/// Environment variable holding the passphrase of --encrypt and --decrypt
+const PASSPHRASE_VAR: &str = "PNGME_PASSPHRASE";
+
+/// What decode found
+pub enum Decoded {
+    Message(String),
+    /// A file, written to `path`
+    Extracted {
+        file_name: Option<String>,
+        path: std::path::PathBuf,
+        size: usize,
+    },
+}
+
 pub fn encode(
-    file_to_encode: &std::path::PathBuf,
-    chunk_type: String,
-    message: &str,
+    file_to_encode: &Path,
+    placement: &Placement,
+    payload: &Payload,
+    compression: Compression,
+    seal: &Seal,
+    destination: &Destination,
 ) -> Result<std::path::PathBuf> {
-    let mut original_png = Png::from_file(file_to_encode)?;
-    let new_chunk_type: ChunkType = ChunkType::from_str(&chunk_type)?;
-    let new_chunk: Chunk = Chunk::new(new_chunk_type, message.as_bytes().to_vec());
-    original_png.append_chunk(new_chunk);
-    let bytes: Vec<u8> = original_png.as_bytes();
-    let new_file = set_new_file_name("encoded", &mut file_to_encode.clone()).unwrap();
+    let bytes = payload.to_bytes(compression)?;
+    let data = match seal {
+        Seal::Plain => bytes,
+        Seal::Passphrase(p) => crypto::seal_with_passphrase(&bytes, p)?,
+        Seal::Recipients(r) => crypto::seal_for_recipients(&bytes, r)?,
+    };
+    let new_file = destination.file_name("encoded", file_to_encode)?;
     info!("new file : {:?}",new_file.to_str());
-    let mut out = File::create(&new_file)?;
-    out.write_all(&bytes)?;
+    match placement {
+        Placement::Chunk {
+            chunk_type,
+            name,
+            max_chunk_size,
+        } => {
+            let new_chunk_type: ChunkType = ChunkType::from_str(chunk_type)?;
+            let ops = split::split(&data, *max_chunk_size, *name)?
+                .into_iter()
+                .map(|piece| Ok(EditOp::Insert(Chunk::try_new(new_chunk_type.clone(), piece)?)))
+                .collect::<Result<Vec<EditOp>>>()?;
+            if ops.len() > 1 {
+                info!("payload split in {} chunks", ops.len());
+            }
+            match name {
+                Some(name) => {
+                    let input = Input::open(file_to_encode)?;
+                    let taken = messages::messages_from(input.reader()?, Some(&new_chunk_type))?
+                        .iter()
+                        .any(|m| m.name.as_deref() == Some(*name));
+                    if taken {
+                        bail!(
+                            "a message called {} is already hidden in the {} chunks, remove it \
+                             first",
+                            name,
+                            chunk_type
+                        );
+                    }
+                    input.write_edited(&new_file, &ops, destination.preserve_mtime)?;
+                }
+                None => write_edited(file_to_encode, &new_file, &ops, destination.preserve_mtime)?,
+            }
+        }
+        Placement::Lsb { key, bits } => {
+            let input = Input::open(file_to_encode)?;
+            let png = input.read_png()?;
+            let embedded = match lsb::embed(&png, &data, *key, *bits) {
+                Err(e @ PngError::PayloadTooLarge { limit }) => {
+                    return Err(anyhow::Error::new(e).context(format!(
+                        "the payload takes {} bytes once encoded, the pixels hold {} with {} \
+                         bit(s) per sample, see pngme capacity",
+                        data.len(),
+                        limit,
+                        bits
+                    )))
+                }
+                result => result?,
+            };
+            let idat = embedded.chunks_by_type("IDAT").cloned().collect();
+            let ops = [EditOp::ReplaceImageData(idat)];
+            input.write_edited(&new_file, &ops, destination.preserve_mtime)?;
+        }
+        Placement::Trailer => {
+            let input = Input::open(file_to_encode)?;
+            // the trailer may be another tool's data, it isn't overwritten
+            let mut reader = PngReader::new(input.reader()?)?;
+            while reader.next_header()?.is_some() {}
+            let trailer_len = reader.copy_trailer(&mut io::sink())?;
+            if trailer_len > 0 {
+                bail!(
+                    "{} bytes already follow IEND, remove them first with remove --trailer",
+                    trailer_len
+                );
+            }
+            let ops = [EditOp::SetTrailer(trailer::frame(&data)?)];
+            input.write_edited(&new_file, &ops, destination.preserve_mtime)?;
+        }
+        Placement::IdatSlack => {
+            let input = Input::open(file_to_encode)?;
+            let png = input.read_png()?;
+            let embedded = slack::embed(&png, &data)?;
+            let idat = embedded.chunks_by_type("IDAT").cloned().collect();
+            let ops = [EditOp::ReplaceImageData(idat)];
+            input.write_edited(&new_file, &ops, destination.preserve_mtime)?;
+        }
+    }
     Ok(new_file)
 }
 
-pub fn decode(file_to_decode: &std::path::PathBuf, chunk_type: String) -> Result<String> {
-    let original_png = Png::from_file(file_to_decode)?;
-    let chunk_with_msg = original_png.chunk_by_type(&chunk_type);
-    if let Some(c) = chunk_with_msg {
-        let message = c.data_as_string()?;
-        info!("decrypted message : {}",message);
-        return Ok(message);
+/// The payload to hide : the secret message, or the content of `payload_file`
+pub fn read_payload(message: Option<&str>, payload_file: Option<&Path>) -> Result<Payload> {
+    match (message, payload_file) {
+        (Some(m), None) => Ok(Payload::message(m)),
+        (None, Some(path)) => {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow::anyhow!("no file name in {}", path.display()))?;
+            Ok(Payload::file(name, std::fs::read(path)?))
+        }
+        _ => bail!("either a secret message or a payload file is needed"),
     }
-    warn!("no message encoded");
-    let e = "no message encoded".to_string();
-    Err(Error::msg(e))
 }
 
+/// Find the payload hidden in `source`. A file is written to `extract_to`, or
+/// in it under its original name if it is a directory.
+pub fn decode(
+    file_to_decode: &Path,
+    source: &Source,
+    unseal: &Unseal,
+    extract_to: Option<&Path>,
+    max_size: u64,
+) -> Result<Decoded> {
+    let original_png = read_png(file_to_decode)?;
+    let found = match source {
+        Source::Chunk { chunk_type, name } => {
+            let chunk_type = ChunkType::from_str(chunk_type)?;
+            messages::read_message(&original_png, &chunk_type, *name)
+        }
+        Source::Lsb { key, bits } => lsb::extract(&original_png, *key, *bits),
+        Source::Trailer => trailer::unframe(original_png.trailer()).map(|data| data.to_vec()),
+        Source::IdatSlack => slack::extract(&original_png),
+    };
+    let sealed = match found {
+        Err(
+            e @ (PngError::ChunkNotFound(_) | PngError::MessageNotFound(_) | PngError::NoTrailer),
+        ) => {
+            warn!("no message encoded");
+            return Err(e.into());
+        }
+        result => result?,
+    };
+    let data = match unseal {
+        Unseal::Passphrase(p) => crypto::open_with_passphrase(&sealed, p)?,
+        Unseal::Identity(i) => crypto::open_with_identity(&sealed, i)?,
+        Unseal::Plain => match crypto::seal_method(&sealed) {
+            Some(SealMethod::Passphrase) => {
+                bail!("the message is encrypted, decode it with --decrypt")
+            }
+            Some(SealMethod::Recipients) => {
+                bail!("the message is encrypted, decode it with --identity")
+            }
+            None => sealed,
+        },
+    };
+    let payload = Payload::from_bytes_limited(&data, max_size)?;
+    if let Some(path) = extract_to {
+        return extract(payload, path);
+    }
+    if let Some(name) = &payload.file_name {
+        bail!(
+            "the payload is the file {} ({} bytes), extract it with --extract-to",
+            name,
+            payload.content.len()
+        );
+    }
+    let message = payload.text()?;
+    info!("decrypted message : {}",message);
+    Ok(Decoded::Message(message))
+}
+
+/// Write the content of the payload to `path`, or in it if it is a directory.
+/// An existing file is never overwritten.
+fn extract(payload: Payload, path: &Path) -> Result<Decoded> {
+    let mut path = path.to_path_buf();
+    if path.is_dir() {
+        let name = payload
+            .file_name
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("no file name in the payload, give a file path"))?;
+        // the stored name comes from the image : never a path out of `path`
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            bail!("the payload is named {:?}, not a plain file name, give a file path", name);
+        }
+        path.push(name);
+    }
+    let mut file = match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            bail!("{} already exists, it isn't overwritten", path.display())
+        }
+        file => file?,
+    };
+    file.write_all(&payload.content)?;
+    info!("payload extracted to {}", path.display());
+    Ok(Decoded::Extracted {
+        file_name: payload.file_name,
+        path,
+        size: payload.content.len(),
+    })
+}
+
+/// Remove the first message of type `chunk_type`, the messages called `name`
+/// if given, or every chunk of this type if `all`. Without `chunk_type`, the
+/// data after `IEND` is removed instead.
 pub fn remove(
-    file_to_clean: &std::path::PathBuf,
-    chunk_type: String,
+    file_to_clean: &Path,
+    chunk_type: Option<String>,
+    name: Option<&str>,
+    all: bool,
+    destination: &Destination,
 ) -> Result<std::path::PathBuf> {
-    let mut original_png = Png::from_file(file_to_clean)?;
-    let _r = original_png.remove_chunk(&chunk_type);
-    let bytes = original_png.as_bytes();
-    let new_png = Png::try_from(bytes.as_slice())?;
-    let new_file = set_new_file_name("cleaned", &mut file_to_clean.clone()).unwrap();
+    let chunk_type = chunk_type.map(|t| ChunkType::from_str(&t)).transpose()?;
+    let new_file = destination.file_name("cleaned", file_to_clean)?;
     info!("new file : {:?}",new_file.to_str());
-    let mut out = File::create(&new_file)?;
-    out.write_all(&new_png.as_bytes())?;
+    let ops = match (chunk_type, name) {
+        (None, _) => [EditOp::RemoveTrailer],
+        (Some(chunk_type), _) if all => [EditOp::RemoveAll(chunk_type)],
+        (Some(chunk_type), Some(name)) => [EditOp::RemoveNamed(chunk_type, name.to_string())],
+        (Some(chunk_type), None) => [EditOp::RemovePayload(chunk_type)],
+    };
+    write_edited(file_to_clean, &new_file, &ops, destination.preserve_mtime)?;
+    Ok(new_file)
+}
+
+/// Print the messages hidden in the chunks of `chunk_type`, or in every
+/// private ancillary chunk
+pub fn list_messages(file: &Path, chunk_type: Option<&str>, format: Format) -> Result<()> {
+    let png = read_png(file)?;
+    let chunk_type = chunk_type.map(ChunkType::from_str).transpose()?;
+    let found: Vec<MessageInfo> = messages::messages(&png, chunk_type.as_ref());
+    if format == Format::Json {
+        let value = json!({"file": file, "messages": found});
+        output::json(&value, false);
+        return Ok(());
+    }
+    println!("{} : {} message(s)", file.display(), found.len());
+    if found.is_empty() {
+        return Ok(());
+    }
+    println!();
+    println!(
+        "{:<4} {:<20} {:>10} {:>6}  {:<9} {:<8} chunks",
+        "type", "name", "size", "pieces", "encrypted", "complete"
+    );
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    for m in &found {
+        let chunks: Vec<String> = m.chunks.iter().map(|i| i.to_string()).collect();
+        println!(
+            "{:<4} {:<20} {:>10} {:>6}  {:<9} {:<8} {}",
+            m.chunk_type,
+            m.name.as_deref().unwrap_or("-"),
+            m.size,
+            m.chunks.len(),
+            yes_no(m.encrypted),
+            yes_no(m.complete),
+            chunks.join(",")
+        );
+    }
+    Ok(())
+}
+
+/// How many bytes one method can hide, `None` if there is no limit
+#[derive(Serialize)]
+struct MethodCapacity {
+    method: String,
+    capacity: Option<usize>,
+    /// Why the method can't be used with this image
+    unsupported: Option<String>,
+}
+
+/// Why data can't be hidden after the zlib stream of `png`, if it can't
+fn slack_in_use(png: &Png) -> Option<String> {
+    let image_data = match pngme::idat::concat(png) {
+        Ok(data) => data,
+        Err(e) => return Some(e.to_string()),
+    };
+    match pngme::idat::stream_len(&image_data) {
+        Ok(len) if len == image_data.len() => None,
+        Ok(len) => Some(format!("{} bytes already follow the zlib stream", image_data.len() - len)),
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+/// Why nothing is hidden in the palette : the row is listed anyway, so that
+/// indexed images don't look like they were forgotten
+fn palette_unsupported(ihdr: &Ihdr) -> String {
+    if ihdr.color_type() != ColorType::Indexed {
+        return format!("no palette in a {} image", ihdr.color_type());
+    }
+    "not implemented : reordering PLTE means recoding every pixel index, and the lsb of \
+     an index changes the colour"
+        .to_string()
+}
+
+/// Print how many bytes of content each method can hide in the image, once
+/// the headers of the encryption and of a file payload are counted
+pub fn capacity(
+    file: &Path,
+    seal_overhead: usize,
+    payload_file: Option<&Path>,
+    format: Format,
+) -> Result<()> {
+    let png = read_png(file)?;
+    let ihdr = png.header_info()?;
+    let payload = payload_file.map(|path| read_payload(None, Some(path))).transpose()?;
+    let name_len = payload
+        .as_ref()
+        .and_then(|p| p.file_name.as_ref())
+        .map_or(0, |n| n.len());
+    let overhead = seal_overhead + payload.as_ref().map_or(0, |_| payload::HEADER_LEN + name_len);
+
+    let mut methods = vec![
+        MethodCapacity {
+            method: "chunk".to_string(),
+            capacity: None,
+            unsupported: None,
+        },
+        MethodCapacity {
+            method: "trailer".to_string(),
+            capacity: None,
+            unsupported: match png.trailer().len() {
+                0 => None,
+                len => Some(format!("{} bytes already follow IEND", len)),
+            },
+        },
+        MethodCapacity {
+            method: "idat-slack".to_string(),
+            capacity: None,
+            unsupported: slack_in_use(&png),
+        },
+        MethodCapacity {
+            method: "palette".to_string(),
+            capacity: Some(0),
+            unsupported: Some(palette_unsupported(&ihdr)),
+        },
+    ];
+    for bits in 1..=lsb::MAX_BITS {
+        let method = format!("lsb --lsb-bits {}", bits);
+        match lsb::capacity(&png, bits) {
+            Ok(bytes) => methods.push(MethodCapacity {
+                method,
+                capacity: Some(bytes.saturating_sub(overhead)),
+                unsupported: None,
+            }),
+            Err(PngError::UnsupportedImage(reason)) => {
+                methods.push(MethodCapacity {
+                    method: "lsb".to_string(),
+                    capacity: Some(0),
+                    unsupported: Some(reason),
+                });
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let size = payload.as_ref().map(|p| p.content.len());
+    if format == Format::Json {
+        let value = json!({
+            "file": file,
+            "header": ihdr,
+            "overhead": overhead,
+            "payload_size": size,
+            "methods": methods,
+        });
+        output::json(&value, false);
+        return Ok(());
+    }
+    println!("{} : {}", file.display(), ihdr);
+    println!("overhead : {} bytes", overhead);
+    if let Some(size) = size {
+        println!("payload : {} bytes", size);
+    }
+    println!();
+    println!("{:<18} {:>12}  fits", "method", "capacity");
+    for m in &methods {
+        let capacity = match (&m.unsupported, m.capacity) {
+            (Some(_), _) => "-".to_string(),
+            (None, Some(bytes)) => bytes.to_string(),
+            (None, None) => "no limit".to_string(),
+        };
+        let fits = match (&m.unsupported, size) {
+            (Some(reason), _) => reason.clone(),
+            (None, Some(size)) if m.capacity.is_some_and(|c| c < size) => "no".to_string(),
+            (None, Some(_)) => "yes".to_string(),
+            (None, None) => String::new(),
+        };
+        println!("{:<18} {:>12}  {}", m.method, capacity, fits);
+    }
+    Ok(())
+}
+
+pub fn print(file_to_print: &Path, format: Format) -> Result<()> {
+    let original_png = read_png(file_to_print)?;
+    let summaries = original_png.summaries();
+    if format == Format::Json {
+        let value = json!({
+            "file": file_to_print,
+            "header": original_png.header_info().ok(),
+            "chunks": summaries,
+        });
+        output::json(&value, false);
+        return Ok(());
+    }
+    println!("{} : {} chunks", file_to_print.display(), summaries.len());
+    match original_png.header_info() {
+        Ok(ihdr) => println!("IHDR : {}", ihdr),
+        Err(e) => println!("IHDR : {}", e),
+    }
+    println!();
+    println!(
+        "{:>5} {:>10} {:<4} {:>10} {:>10}  {:<9} {:<7} safe-to-copy",
+        "index", "offset", "type", "length", "crc", "critical", "public"
+    );
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    for s in &summaries {
+        println!(
+            "{:>5} {:>10} {:<4} {:>10} 0x{:08x}  {:<9} {:<7} {}",
+            s.index,
+            s.offset,
+            s.chunk_type,
+            s.length,
+            s.crc,
+            yes_no(s.critical),
+            yes_no(s.public),
+            yes_no(s.safe_to_copy)
+        );
+        if let Some(decoded) = &s.decoded {
+            println!("{:>16} {}", "", decoded);
+        }
+    }
+    Ok(())
+}
+
+/// Print every violation of the spec found in the file and return the worst severity
+pub fn check(file_to_check: &Path, format: Format) -> Result<Option<Severity>> {
+    let findings = pngme::check(open_input(file_to_check)?)?;
+    let worst = findings.iter().map(|f| f.severity()).max();
+    if format == Format::Json {
+        let value = json!({
+            "file": file_to_check,
+            "worst": worst,
+            "findings": findings,
+        });
+        output::json(&value, false);
+        return Ok(worst);
+    }
+    for f in &findings {
+        println!("{}", f);
+    }
+    match worst {
+        None => println!("OK : no error found in {}", file_to_check.display()),
+        Some(w) => println!(
+            "{} : {} problem(s) found in {}",
+            w,
+            findings.len(),
+            file_to_check.display()
+        ),
+    }
+    Ok(worst)
+}
+
+/// Scan each file for signs of hidden data and print its report. A file that
+/// can't be scanned is reported and the next ones are still scanned : returns
+/// the first such error once they are all done, else the worst verdict.
+pub fn scan(files: &[PathBuf], format: Format) -> Result<Verdict> {
+    let mut worst = Verdict::Clean;
+    let mut failure = None;
+    let mut reports = Vec::new();
+    for file in files {
+        let report = match open_input(file).and_then(|input| Ok(pngme::scan::scan(input)?)) {
+            Ok(report) => report,
+            Err(e) => {
+                error!("error {} when scanning file {}", e, file.display());
+                if format == Format::Json {
+                    let error = json!({"code": output::error_code(&e), "message": e.to_string()});
+                    reports.push(json!({"file": file, "error": error}));
+                }
+                failure.get_or_insert(e);
+                continue;
+            }
+        };
+        worst = worst.max(report.verdict);
+        if format == Format::Json {
+            reports.push(json!({"file": file, "report": report}));
+            continue;
+        }
+        println!("{} : {}, score {}", file.display(), report.verdict, report.score);
+        for s in &report.suspicions {
+            println!("  {}", s);
+        }
+        if let Some(reason) = &report.statistics_skipped {
+            println!("  pixels not tested : {}", reason);
+        }
+    }
+    if format == Format::Json {
+        output::json(&json!({"files": reports}), false);
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(worst),
+    }
+}
+
+/// Write a new secret key to `output`, or stdout, and return its public key :
+/// an ed25519 key if `signing`, otherwise an X25519 one
+pub fn keygen(output: Option<&Path>, signing: bool) -> Result<String> {
+    let (secret, public) = if signing {
+        let key = SigningKey::generate()?;
+        (key.to_string(), key.verifying_key().to_string())
+    } else {
+        let identity = Identity::generate()?;
+        (identity.to_string(), identity.recipient().to_string())
+    };
+    match output {
+        Some(path) => {
+            let mut options = File::options();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options.open(path)?.write_all(secret.as_bytes())?;
+        }
+        None => print!("{}", secret),
+    }
+    Ok(public)
+}
+
+/// Add a signature of the critical chunks and the `include` ones, replacing
+/// the previous signature if any
+pub fn sign(
+    file_to_sign: &Path,
+    key: &Path,
+    include: &[String],
+    destination: &Destination,
+) -> Result<std::path::PathBuf> {
+    let key = SigningKey::from_str(&std::fs::read_to_string(key)?)?;
+    let include = include
+        .iter()
+        .map(|t| ChunkType::from_str(t))
+        .collect::<Result<Vec<_>, _>>()?;
+    let new_file = destination.file_name("signed", file_to_sign)?;
+    let input = Input::open(file_to_sign)?;
+    // the previous signatures are removed, only their headers are read
+    let signature_type = ChunkType::try_from(signature::CHUNK_TYPE)?;
+    let mut ops = Vec::new();
+    let mut reader = PngReader::new(input.reader()?)?;
+    while let Some(header) = reader.next_header()? {
+        if *header.chunk_type() == signature_type {
+            ops.push(EditOp::Remove(signature_type.clone()));
+        }
+    }
+    ops.push(EditOp::Insert(signature::sign_from(input.reader()?, &key, &include)?));
+    info!("new file : {:?}", new_file.to_str());
+    input.write_edited(&new_file, &ops, destination.preserve_mtime)?;
     Ok(new_file)
 }
 
-pub fn print(file_to_print: &std::path::PathBuf) -> Result<()> {
-    let original_png = Png::from_file(file_to_print)?;
-    let bytes = original_png.as_bytes();
-    info!("{:?}",bytes);
+/// Check the signature of the file against `pubkey`
+pub fn verify(file_to_verify: &Path, pubkey: &str) -> Result<Signed> {
+    let key = VerifyingKey::from_str(pubkey)?;
+    let input = Input::open(file_to_verify)?;
+    Ok(signature::verify_from(input.reader()?, &key)?)
+}
+
+/// Read the secret key written by keygen
+pub fn read_identity(path: &Path) -> Result<Identity> {
+    Ok(Identity::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Passphrase from the PNGME_PASSPHRASE environment variable, or else asked on
+/// the terminal, twice if `confirm`
+pub fn passphrase(confirm: bool) -> Result<String> {
+    let p = match std::env::var(PASSPHRASE_VAR) {
+        Ok(p) => p,
+        Err(_) => {
+            let p = rpassword::prompt_password("passphrase : ")?;
+            if confirm && rpassword::prompt_password("passphrase again : ")? != p {
+                bail!("the passphrases don't match");
+            }
+            p
+        }
+    };
+    if p.is_empty() {
+        bail!("empty passphrase");
+    }
+    Ok(p)
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO
+}
+
+fn read_png(path: &Path) -> Result<Png> {
+    if is_stdio(path) {
+        return Ok(Png::from_reader(io::stdin().lock())?);
+    }
+    Ok(Png::from_file(path)?)
+}
+
+fn open_input(path: &Path) -> Result<Box<dyn Read>> {
+    if is_stdio(path) {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+/// Apply `ops` to `input` and write the result to `output`, each of them may
+/// be stdio. Nothing is written if an op fails : see [`write_edited_from`]
+fn write_edited(input: &Path, output: &Path, ops: &[EditOp], preserve_mtime: bool) -> Result<()> {
+    if is_stdio(input) || is_stdio(output) {
+        write_edited_from(open_input(input)?, output, ops)
+    } else {
+        Ok(edit_file(input, output, ops, preserve_mtime)?)
+    }
+}
+
+/// Edit the PNG read from `reader` into `output`. A file is only replaced
+/// once the edit succeeded, and stdout gets the PNG from a temporary file
+/// once it is complete, rather than a PNG followed by an error.
+fn write_edited_from<R: Read>(reader: R, output: &Path, ops: &[EditOp]) -> Result<()> {
+    if !is_stdio(output) {
+        return Ok(edit_to_file(reader, output, ops)?);
+    }
+    let temp = edit(reader, BufWriter::new(tempfile::tempfile()?), ops)?;
+    let mut temp = temp.into_inner().map_err(|e| e.into_error())?;
+    temp.rewind()?;
+    let mut stdout = io::stdout().lock();
+    io::copy(&mut temp, &mut stdout)?;
+    stdout.flush()?;
     Ok(())
 }
 
+/// The PNG to edit, as a file which can be read more than once : stdin is
+/// first copied to a temporary file
+struct Input {
+    path: PathBuf,
+    /// The copy of stdin, removed when dropped
+    spooled: Option<tempfile::TempPath>,
+}
+
+impl Input {
+    fn open(path: &Path) -> Result<Input> {
+        if !is_stdio(path) {
+            return Ok(Input { path: path.to_path_buf(), spooled: None });
+        }
+        let mut temp = tempfile::NamedTempFile::new()?;
+        io::copy(&mut io::stdin().lock(), &mut temp)?;
+        let spooled = temp.into_temp_path();
+        Ok(Input { path: spooled.to_path_buf(), spooled: Some(spooled) })
+    }
+
+    fn reader(&self) -> Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(&self.path)?))
+    }
+
+    /// The whole image, only for the methods which change the pixels
+    fn read_png(&self) -> Result<Png> {
+        Ok(Png::from_file(&self.path)?)
+    }
+
+    /// Apply `ops` and write the result to `output`, like write_edited
+    fn write_edited(&self, output: &Path, ops: &[EditOp], preserve_mtime: bool) -> Result<()> {
+        match self.spooled {
+            // the copy of stdin must not give its permissions to the output
+            Some(_) => write_edited_from(self.reader()?, output, ops),
+            None => write_edited(&self.path, output, ops, preserve_mtime),
+        }
+    }
+}
+
+impl Destination<'_> {
+    /// True if the PNG is written to stdout, which then can't carry anything else
+    pub fn to_stdout(&self, input: &Path) -> bool {
+        !self.in_place && is_stdio(self.output.unwrap_or(input))
+    }
+
+    /// File to write : `output` if given, the input itself when editing in
+    /// place, stdout when reading stdin, otherwise a new file next to the input
+    fn file_name(&self, prefix: &str, input: &Path) -> Result<std::path::PathBuf> {
+        if self.in_place {
+            if is_stdio(input) {
+                bail!("--in-place needs a file, not stdin");
+            }
+            return Ok(input.to_path_buf());
+        }
+        match self.output {
+            Some(o) => Ok(o.to_path_buf()),
+            None if is_stdio(input) => Ok(STDIO.into()),
+            None => set_new_file_name(prefix, &mut input.to_path_buf()),
+        }
+    }
+}
+
 fn set_new_file_name(
     prefix: &str,
     new_file: &mut std::path::PathBuf,
 ) -> Result<std::path::PathBuf> {
-    let name = new_file.file_stem().unwrap();
-    let ext = new_file.extension().unwrap();
+    let invalid = || anyhow::anyhow!("no UTF-8 file name in {}", new_file.display());
+    let name = new_file.file_stem().and_then(|n| n.to_str()).ok_or_else(invalid)?;
+    let ext = new_file.extension().map(|e| e.to_str().ok_or_else(invalid)).transpose()?;
     let now: DateTime<Local> = Local::now();
     let now = format!("{}", now.format("_%H%M%S%d%m%Y_"));
-    let new_full_name =
-        prefix.to_owned() + &now + name.to_str().unwrap() + "." + ext.to_str().unwrap();
+    let mut new_full_name = prefix.to_owned() + &now + name;
+    if let Some(ext) = ext {
+        new_full_name = new_full_name + "." + ext;
+    }
     new_file.set_file_name(new_full_name);
     Ok(new_file.to_path_buf())
 }
@@ -0,0 +1,582 @@
+use crate::{PngError, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt::Display;
+use std::str::FromStr;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// First bytes of every encrypted message
+pub const MAGIC: [u8; 5] = *b"PNGME";
+/// Version of the layout of the encrypted messages
+pub const VERSION: u8 = 1;
+/// Method used to get the key : derived from a passphrase
+const METHOD_PASSPHRASE: u8 = 1;
+/// Method used to get the key : random, wrapped for each X25519 recipient
+const METHOD_RECIPIENTS: u8 = 2;
+/// Key derivation function : Argon2id, version 0x13
+const KDF_ARGON2ID: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// magic, version, method, kdf, 3 x u32 costs, salt, nonce
+const PASSPHRASE_HEADER_LEN: usize = MAGIC.len() + 3 + 12 + SALT_LEN + NONCE_LEN;
+const KEY_LEN: usize = 32;
+/// ephemeral public key, wrapped file key
+const STANZA_LEN: usize = KEY_LEN + KEY_LEN + TAG_LEN;
+/// Context of the HKDF deriving the wrapping key of a recipient
+const WRAP_INFO: &[u8] = b"pngme x25519 v1";
+
+/// Bytes added to a message by [`seal_with_passphrase`]
+pub const PASSPHRASE_OVERHEAD: usize = PASSPHRASE_HEADER_LEN + TAG_LEN;
+
+/// Upper bounds on the costs read from a message, so that a forged header
+/// can't make us allocate more than 256 MiB or loop for hours
+const MAX_M_COST: u32 = 1 << 18;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 16;
+
+/// Argon2id costs used to derive the key from the passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory size, in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    /// The Argon2 defaults : 19 MiB, 2 iterations, 1 lane
+    fn default() -> Self {
+        KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// How the key of an encrypted message is obtained
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SealMethod {
+    /// Derived from a passphrase, see [`seal_with_passphrase`]
+    Passphrase,
+    /// Wrapped for a list of public keys, see [`seal_for_recipients`]
+    Recipients,
+}
+
+/// True if `data` starts like a message encrypted by this module : the magic,
+/// then a known version and method
+pub fn is_sealed(data: &[u8]) -> bool {
+    seal_method(data).is_some()
+}
+
+/// Method of an encrypted message, `None` if `data` is not one or comes from
+/// an unknown version
+pub fn seal_method(data: &[u8]) -> Option<SealMethod> {
+    if !data.starts_with(&MAGIC) || data.get(MAGIC.len()) != Some(&VERSION) {
+        return None;
+    }
+    match data.get(MAGIC.len() + 1) {
+        Some(&METHOD_PASSPHRASE) => Some(SealMethod::Passphrase),
+        Some(&METHOD_RECIPIENTS) => Some(SealMethod::Recipients),
+        _ => None,
+    }
+}
+
+/// X25519 public key of someone who can read a message, written as 64 hex digits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+/// X25519 secret key opening the messages sealed for its [`Recipient`]
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// A new random key pair
+    pub fn generate() -> Result<Identity> {
+        let mut bytes = [0u8; KEY_LEN];
+        random_bytes(&mut bytes)?;
+        Ok(Identity(StaticSecret::from(bytes)))
+    }
+
+    /// The public key to give to the senders
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = from_hex(s.trim()).ok_or_else(|| invalid("a public key is 64 hex digits"))?;
+        Ok(Recipient(PublicKey::from(bytes)))
+    }
+}
+
+impl Display for Identity {
+    /// The content of a key file : the public key as a comment, then the secret key
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# public key : {}", self.recipient())?;
+        writeln!(f, "{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = PngError;
+
+    /// Parse a key file : the first line which is neither empty nor a `#` comment
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Identity(StaticSecret::from(parse_key_file(s)?)))
+    }
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Identity({})", self.recipient())
+    }
+}
+
+/// Encrypt `plaintext` with a key derived from `passphrase`, with the default
+/// Argon2id costs
+pub fn seal_with_passphrase(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    seal_with_passphrase_params(plaintext, passphrase, KdfParams::default())
+}
+
+/// Encrypt `plaintext` with ChaCha20-Poly1305 and a key derived from
+/// `passphrase` with Argon2id.
+///
+/// The result starts with a header holding the format version, the KDF costs,
+/// the random salt and nonce, all of them authenticated with the ciphertext.
+///
+/// ```text
+/// "PNGME" | version | method = 1 | kdf = 1 | m_cost | t_cost | p_cost | salt (16) | nonce (12) | ciphertext + tag (16)
+/// ```
+///
+/// Returns [`PngError::InvalidPayload`] if the costs are above the limits
+/// checked when opening the message, it couldn't be opened.
+pub fn seal_with_passphrase_params(
+    plaintext: &[u8],
+    passphrase: &str,
+    params: KdfParams,
+) -> Result<Vec<u8>> {
+    if params.m_cost > MAX_M_COST || params.t_cost > MAX_T_COST || params.p_cost > MAX_P_COST {
+        return Err(invalid("key derivation costs above the allowed limits"));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    random_bytes(&mut salt)?;
+    random_bytes(&mut nonce)?;
+
+    let mut sealed = Vec::with_capacity(PASSPHRASE_HEADER_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend_from_slice(&MAGIC);
+    sealed.extend_from_slice(&[VERSION, METHOD_PASSPHRASE, KDF_ARGON2ID]);
+    sealed.extend_from_slice(&params.m_cost.to_be_bytes());
+    sealed.extend_from_slice(&params.t_cost.to_be_bytes());
+    sealed.extend_from_slice(&params.p_cost.to_be_bytes());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = ChaCha20Poly1305::new(&key);
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &sealed,
+            },
+        )
+        .map_err(|_| invalid("encryption failed"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt a message sealed by [`seal_with_passphrase`].
+///
+/// Returns [`PngError::AuthenticationFailed`] if the passphrase is wrong or
+/// if any byte of the message, header included, was altered.
+pub fn open_with_passphrase(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    check_header(sealed, METHOD_PASSPHRASE, PASSPHRASE_HEADER_LEN)?;
+    let (header, ciphertext) = sealed.split_at(PASSPHRASE_HEADER_LEN);
+    let mut index = MAGIC.len() + 2;
+    if header[index] != KDF_ARGON2ID {
+        return Err(invalid(&format!(
+            "unsupported key derivation function {}",
+            header[index]
+        )));
+    }
+    index += 1;
+    let mut costs = [0u32; 3];
+    for cost in costs.iter_mut() {
+        *cost = u32::from_be_bytes([
+            header[index],
+            header[index + 1],
+            header[index + 2],
+            header[index + 3],
+        ]);
+        index += 4;
+    }
+    let params = KdfParams {
+        m_cost: costs[0],
+        t_cost: costs[1],
+        p_cost: costs[2],
+    };
+    if params.m_cost > MAX_M_COST || params.t_cost > MAX_T_COST || params.p_cost > MAX_P_COST {
+        return Err(invalid("key derivation costs above the allowed limits"));
+    }
+    let salt = &header[index..index + SALT_LEN];
+    let nonce = &header[index + SALT_LEN..];
+
+    let key = derive_key(passphrase, salt, params)?;
+    decrypt(&key, nonce, header, ciphertext)
+}
+
+/// Encrypt `plaintext` so that any of `recipients` can read it with its
+/// [`Identity`], the way `age` does.
+///
+/// A random file key encrypts the message, and is itself wrapped for each
+/// recipient with a key derived from an X25519 exchange between the public
+/// key of the recipient and a new ephemeral key. The header, with the list of
+/// wrapped keys, is authenticated with the ciphertext.
+///
+/// ```text
+/// "PNGME" | version | method = 2 | count | count x (ephemeral key (32) | wrapped key (32 + 16)) | nonce (12) | ciphertext + tag (16)
+/// ```
+pub fn seal_for_recipients(plaintext: &[u8], recipients: &[Recipient]) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u8::MAX as usize {
+        return Err(invalid("between 1 and 255 recipients are needed"));
+    }
+    let mut file_key = Key::default();
+    let mut nonce = [0u8; NONCE_LEN];
+    random_bytes(&mut file_key)?;
+    random_bytes(&mut nonce)?;
+
+    let header_len = MAGIC.len() + 3 + recipients.len() * STANZA_LEN + NONCE_LEN;
+    let mut sealed = Vec::with_capacity(header_len + plaintext.len() + TAG_LEN);
+    sealed.extend_from_slice(&MAGIC);
+    sealed.extend_from_slice(&[VERSION, METHOD_RECIPIENTS, recipients.len() as u8]);
+    for recipient in recipients {
+        let ephemeral = Identity::generate()?;
+        let ephemeral_public = ephemeral.recipient();
+        let shared = ephemeral.0.diffie_hellman(&recipient.0);
+        // a low-order point gives the same secret whatever the ephemeral key
+        if !shared.was_contributory() {
+            return Err(invalid(&format!("{} is not a valid public key", recipient)));
+        }
+        let wrap_key = wrapping_key(shared.as_bytes(), &ephemeral_public, recipient);
+        // each wrapping key is used once, a zero nonce is safe
+        let wrapped = ChaCha20Poly1305::new(&wrap_key)
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(|_| invalid("encryption failed"))?;
+        sealed.extend_from_slice(ephemeral_public.0.as_bytes());
+        sealed.extend_from_slice(&wrapped);
+    }
+    sealed.extend_from_slice(&nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(&file_key)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &sealed,
+            },
+        )
+        .map_err(|_| invalid("encryption failed"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Bytes added to a message by [`seal_for_recipients`] for `count` recipients
+pub fn recipients_overhead(count: usize) -> usize {
+    MAGIC.len() + 3 + count * STANZA_LEN + NONCE_LEN + TAG_LEN
+}
+
+/// Decrypt a message sealed by [`seal_for_recipients`] with the secret key of
+/// one of its recipients.
+///
+/// Returns [`PngError::AuthenticationFailed`] if `identity` is not one of the
+/// recipients or if any byte of the message was altered.
+pub fn open_with_identity(sealed: &[u8], identity: &Identity) -> Result<Vec<u8>> {
+    check_header(sealed, METHOD_RECIPIENTS, MAGIC.len() + 3)?;
+    let count = sealed[MAGIC.len() + 2] as usize;
+    let header_len = MAGIC.len() + 3 + count * STANZA_LEN + NONCE_LEN;
+    check_header(sealed, METHOD_RECIPIENTS, header_len)?;
+    let (header, ciphertext) = sealed.split_at(header_len);
+
+    let own = identity.recipient();
+    let file_key = header[MAGIC.len() + 3..header_len - NONCE_LEN]
+        .chunks_exact(STANZA_LEN)
+        .find_map(|stanza| {
+            let mut ephemeral = [0u8; KEY_LEN];
+            ephemeral.copy_from_slice(&stanza[..KEY_LEN]);
+            let ephemeral = Recipient(PublicKey::from(ephemeral));
+            let shared = identity.0.diffie_hellman(&ephemeral.0);
+            if !shared.was_contributory() {
+                return None;
+            }
+            let wrap_key = wrapping_key(shared.as_bytes(), &ephemeral, &own);
+            ChaCha20Poly1305::new(&wrap_key)
+                .decrypt(&Nonce::default(), &stanza[KEY_LEN..])
+                .ok()
+        })
+        .ok_or(PngError::AuthenticationFailed)?;
+
+    let nonce = &header[header_len - NONCE_LEN..];
+    decrypt(Key::from_slice(&file_key), nonce, header, ciphertext)
+}
+
+/// Key wrapping the file key for `recipient`, derived from the X25519 secret
+/// shared with the ephemeral key
+fn wrapping_key(shared: &[u8; KEY_LEN], ephemeral: &Recipient, recipient: &Recipient) -> Key {
+    let mut salt = [0u8; 2 * KEY_LEN];
+    salt[..KEY_LEN].copy_from_slice(ephemeral.0.as_bytes());
+    salt[KEY_LEN..].copy_from_slice(recipient.0.as_bytes());
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Check the magic, version and method of `sealed`, and that it holds a
+/// header of `header_len` bytes and a tag
+fn check_header(sealed: &[u8], method: u8, header_len: usize) -> Result<()> {
+    if !sealed.starts_with(&MAGIC) {
+        return Err(invalid("not an encrypted message"));
+    }
+    if sealed.len() < header_len + TAG_LEN {
+        return Err(invalid("encrypted message too short"));
+    }
+    let version = sealed[MAGIC.len()];
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "unsupported encrypted message version {}",
+            version
+        )));
+    }
+    let found = sealed[MAGIC.len() + 1];
+    if found != method {
+        return Err(invalid(&format!("unexpected encryption method {}", found)));
+    }
+    Ok(())
+}
+
+fn decrypt(key: &Key, nonce: &[u8], header: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| PngError::AuthenticationFailed)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Key> {
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| invalid(&format!("invalid key derivation costs : {}", e)))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let mut key = Key::default();
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| invalid(&format!("key derivation failed : {}", e)))?;
+    Ok(key)
+}
+
+pub(crate) fn random_bytes(buf: &mut [u8]) -> Result<()> {
+    getrandom::getrandom(buf).map_err(|e| PngError::Io(std::io::Error::other(e.to_string())))
+}
+
+/// The 32 bytes of the secret key written as 64 hex digits on the first line of
+/// `s` which is neither empty nor a `#` comment
+pub(crate) fn parse_key_file(s: &str) -> Result<[u8; KEY_LEN]> {
+    let line = s
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .ok_or_else(|| invalid("no secret key in the key file"))?;
+    from_hex(line).ok_or_else(|| invalid("a secret key is 64 hex digits"))
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(s: &str) -> Option<[u8; KEY_LEN]> {
+    if s.len() != 2 * KEY_LEN || !s.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; KEY_LEN];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn invalid(reason: &str) -> PngError {
+    PngError::InvalidPayload(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap costs, the defaults are slow in debug builds
+    const PARAMS: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let sealed = seal_with_passphrase_params(b"secret message", "passphrase", PARAMS).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(sealed.len(), PASSPHRASE_HEADER_LEN + 14 + TAG_LEN);
+        assert_eq!(sealed.len(), 14 + PASSPHRASE_OVERHEAD);
+        let opened = open_with_passphrase(&sealed, "passphrase").unwrap();
+        assert_eq!(opened, b"secret message");
+    }
+
+    #[test]
+    fn test_random_salt_and_nonce() {
+        let a = seal_with_passphrase_params(b"secret", "passphrase", PARAMS).unwrap();
+        let b = seal_with_passphrase_params(b"secret", "passphrase", PARAMS).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let sealed = seal_with_passphrase_params(b"secret", "passphrase", PARAMS).unwrap();
+        let opened = open_with_passphrase(&sealed, "other");
+        assert!(matches!(opened, Err(PngError::AuthenticationFailed)));
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let sealed = seal_with_passphrase_params(b"secret", "passphrase", PARAMS).unwrap();
+        // a byte of the salt, of the ciphertext and of the tag
+        for index in [PASSPHRASE_HEADER_LEN - 20, PASSPHRASE_HEADER_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            let opened = open_with_passphrase(&tampered, "passphrase");
+            assert!(matches!(opened, Err(PngError::AuthenticationFailed)));
+        }
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let sealed = seal_with_passphrase_params(b"secret", "passphrase", PARAMS).unwrap();
+
+        let mut other_version = sealed.clone();
+        other_version[MAGIC.len()] = 2;
+        assert!(!is_sealed(&other_version));
+        let opened = open_with_passphrase(&other_version, "passphrase");
+        assert!(matches!(opened, Err(PngError::InvalidPayload(_))));
+        let mut other_method = sealed.clone();
+        other_method[MAGIC.len() + 1] = 9;
+        assert!(!is_sealed(&other_method));
+        assert_eq!(seal_method(&other_method), None);
+
+        let costs = KdfParams {
+            m_cost: MAX_M_COST + 1,
+            ..PARAMS
+        };
+        assert!(seal_with_passphrase_params(b"secret", "passphrase", costs).is_err());
+
+        let mut huge_cost = sealed.clone();
+        huge_cost[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let opened = open_with_passphrase(&huge_cost, "passphrase");
+        assert!(matches!(opened, Err(PngError::InvalidPayload(_))));
+
+        let opened = open_with_passphrase(&sealed[..20], "passphrase");
+        assert!(matches!(opened, Err(PngError::InvalidPayload(_))));
+        let opened = open_with_passphrase(b"plain text", "passphrase");
+        assert!(matches!(opened, Err(PngError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn test_recipients_round_trip() {
+        let alice = Identity::generate().unwrap();
+        let bob = Identity::generate().unwrap();
+        let recipients = [alice.recipient(), bob.recipient()];
+        let sealed = seal_for_recipients(b"secret message", &recipients).unwrap();
+        assert_eq!(sealed.len(), 14 + recipients_overhead(2));
+
+        assert_eq!(seal_method(&sealed), Some(SealMethod::Recipients));
+        assert_eq!(open_with_identity(&sealed, &alice).unwrap(), b"secret message");
+        assert_eq!(open_with_identity(&sealed, &bob).unwrap(), b"secret message");
+    }
+
+    #[test]
+    fn test_not_a_recipient() {
+        let alice = Identity::generate().unwrap();
+        let eve = Identity::generate().unwrap();
+        let sealed = seal_for_recipients(b"secret", &[alice.recipient()]).unwrap();
+        let opened = open_with_identity(&sealed, &eve);
+        assert!(matches!(opened, Err(PngError::AuthenticationFailed)));
+    }
+
+    #[test]
+    fn test_recipients_tampering_is_detected() {
+        let alice = Identity::generate().unwrap();
+        let sealed = seal_for_recipients(b"secret", &[alice.recipient()]).unwrap();
+        // the ephemeral key, the wrapped key, the nonce and the ciphertext
+        for index in [8, 8 + KEY_LEN, 8 + STANZA_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            let opened = open_with_identity(&tampered, &alice);
+            assert!(matches!(opened, Err(PngError::AuthenticationFailed)));
+        }
+    }
+
+    #[test]
+    fn test_low_order_keys() {
+        let zero = Recipient::from_str(&"00".repeat(KEY_LEN)).unwrap();
+        let sealed = seal_for_recipients(b"secret", &[zero]);
+        assert!(matches!(sealed, Err(PngError::InvalidPayload(_))));
+
+        let alice = Identity::generate().unwrap();
+        let mut sealed = seal_for_recipients(b"secret", &[alice.recipient()]).unwrap();
+        sealed[8..8 + KEY_LEN].fill(0);
+        let opened = open_with_identity(&sealed, &alice);
+        assert!(matches!(opened, Err(PngError::AuthenticationFailed)));
+    }
+
+    #[test]
+    fn test_wrong_method() {
+        let alice = Identity::generate().unwrap();
+        let sealed = seal_with_passphrase_params(b"secret", "passphrase", PARAMS).unwrap();
+        assert_eq!(seal_method(&sealed), Some(SealMethod::Passphrase));
+        let opened = open_with_identity(&sealed, &alice);
+        assert!(matches!(opened, Err(PngError::InvalidPayload(_))));
+        assert!(seal_for_recipients(b"secret", &[]).is_err());
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let identity = Identity::generate().unwrap();
+        let parsed = Identity::from_str(&identity.to_string()).unwrap();
+        assert_eq!(parsed.recipient(), identity.recipient());
+
+        let recipient = identity.recipient();
+        assert_eq!(recipient.to_string().len(), 64);
+        assert_eq!(Recipient::from_str(&recipient.to_string()).unwrap(), recipient);
+        assert!(Recipient::from_str("not hex").is_err());
+        assert!(Identity::from_str("# only a comment\n").is_err());
+    }
+}
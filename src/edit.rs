@@ -0,0 +1,541 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::reader::PngReader;
+use crate::split::Piece;
+use crate::writer::PngWriter;
+use crate::{PngError, Result};
+use std::fs::{File, Permissions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// One change applied by [`edit`] while the PNG is copied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditOp {
+    /// Add a chunk before `IEND`, like [`Png::append_chunk`](crate::Png::append_chunk)
+    Insert(Chunk),
+    /// Remove the first chunk of this type, like [`Png::remove_chunk`](crate::Png::remove_chunk)
+    Remove(ChunkType),
+    /// Replace the first chunk of this type by the given chunk
+    Replace(ChunkType, Chunk),
+    /// Remove the first chunk of this type, and the other pieces of the
+    /// payload if it is a [`Piece`] of a split payload
+    RemovePayload(ChunkType),
+    /// Remove every piece of the messages with this name among the chunks of
+    /// this type
+    RemoveNamed(ChunkType, String),
+    /// Remove every chunk of this type
+    RemoveAll(ChunkType),
+    /// Replace the `IDAT` chunks by these ones, written where the first was
+    ReplaceImageData(Vec<Chunk>),
+    /// Replace the bytes after `IEND` by these ones
+    SetTrailer(Vec<u8>),
+    /// Remove the bytes after `IEND`
+    RemoveTrailer,
+}
+
+/// Copy a PNG from `reader` to `writer` applying `ops` on the fly.
+///
+/// Chunks which are not touched by an operation, `IDAT` included, are copied
+/// byte for byte without being buffered nor having their CRC recomputed, so
+/// memory use doesn't depend on the size of the image. So is the trailer
+/// after `IEND`, unless an operation replaces or removes it. Each `Remove` and
+/// `Replace` applies to one chunk : two `Remove` of the same type remove the
+/// first two chunks of this type.
+///
+/// Returns [`PngError::ChunkNotFound`] if an operation removing or replacing
+/// chunks matched none, [`PngError::MessageNotFound`] for a `RemoveNamed`, or
+/// [`PngError::NoTrailer`] for a `RemoveTrailer`, the output is complete in
+/// this case. Returns [`PngError::InvalidPayload`] if a chunk read to find a
+/// payload starts like a piece but is corrupted.
+pub fn edit<R: Read, W: Write>(reader: R, writer: W, ops: &[EditOp]) -> Result<W> {
+    let mut reader = PngReader::new(reader)?;
+    let mut writer = PngWriter::new(writer)?;
+    let mut applied = vec![false; ops.len()];
+    let mut inserted = false;
+    // split payloads removed, their next pieces are removed as well
+    let mut removed_pieces: Vec<(ChunkType, [u8; 8])> = Vec::new();
+
+    while let Some(header) = reader.next_header()? {
+        if header.chunk_type().bytes() == *b"IEND" && !inserted {
+            write_inserts(&mut writer, ops)?;
+            inserted = true;
+        }
+
+        let chunk_type = header.chunk_type().clone();
+        let image_data = ops.iter().position(|op| matches!(op, EditOp::ReplaceImageData(_)));
+        if let (Some(i), true) = (image_data, chunk_type.bytes() == *b"IDAT") {
+            if let (false, EditOp::ReplaceImageData(chunks)) = (applied[i], &ops[i]) {
+                for chunk in chunks {
+                    writer.write_chunk(chunk)?;
+                }
+                applied[i] = true;
+            }
+            reader.skip_body()?;
+            continue;
+        }
+        if let Some(i) = ops
+            .iter()
+            .position(|op| matches!(op, EditOp::RemoveAll(t) if *t == chunk_type))
+        {
+            applied[i] = true;
+            reader.skip_body()?;
+            continue;
+        }
+
+        // the data is only read when an operation depends on it
+        let named = ops.iter().enumerate().filter_map(|(i, op)| match op {
+            EditOp::RemoveNamed(t, name) if *t == chunk_type => Some((i, name.as_str())),
+            _ => None,
+        });
+        let mut body = None;
+        if named.clone().next().is_some() || removed_pieces.iter().any(|(t, _)| *t == chunk_type)
+        {
+            let chunk = reader.read_body()?;
+            if let Some(piece) = Piece::parse(chunk.data())? {
+                if let Some((i, _)) = named.clone().find(|(_, name)| piece.name == Some(name)) {
+                    applied[i] = true;
+                    continue;
+                }
+                if removed_pieces.contains(&(chunk_type.clone(), piece.id)) {
+                    continue;
+                }
+            }
+            body = Some(chunk);
+        }
+
+        let matching = (0..ops.len()).find(|&i| {
+            !applied[i]
+                && match &ops[i] {
+                    EditOp::Remove(t) | EditOp::Replace(t, _) | EditOp::RemovePayload(t) => {
+                        *t == chunk_type
+                    }
+                    _ => false,
+                }
+        });
+        if let Some(i) = matching {
+            applied[i] = true;
+        }
+        match (matching.map(|i| &ops[i]), body) {
+            (Some(EditOp::Remove(_)), None) => reader.skip_body()?,
+            (Some(EditOp::Remove(_)), Some(_)) => (),
+            (Some(EditOp::Replace(_, chunk)), body) => {
+                if body.is_none() {
+                    reader.skip_body()?;
+                }
+                writer.write_chunk(chunk)?;
+            }
+            (Some(EditOp::RemovePayload(_)), body) => {
+                let chunk = match body {
+                    Some(chunk) => chunk,
+                    None => reader.read_body()?,
+                };
+                if let Some(piece) = Piece::parse(chunk.data())? {
+                    removed_pieces.push((chunk_type, piece.id));
+                }
+            }
+            (_, Some(chunk)) => writer.write_chunk(&chunk)?,
+            (_, None) => writer.copy_from(&mut reader)?,
+        }
+    }
+    if !inserted {
+        write_inserts(&mut writer, ops)?;
+    }
+    let trailer = ops
+        .iter()
+        .position(|op| matches!(op, EditOp::SetTrailer(_) | EditOp::RemoveTrailer));
+    match trailer.map(|i| (i, &ops[i])) {
+        Some((i, EditOp::SetTrailer(trailer))) => {
+            reader.copy_trailer(&mut io::sink())?;
+            writer.write_trailer(trailer)?;
+            applied[i] = true;
+        }
+        Some((i, _)) => applied[i] = reader.copy_trailer(&mut io::sink())? > 0,
+        None => writer.copy_trailer_from(&mut reader)?,
+    }
+
+    let not_found = ops.iter().zip(&applied).find_map(|(op, done)| match op {
+        _ if *done => None,
+        EditOp::Insert(_) | EditOp::SetTrailer(_) => None,
+        EditOp::RemoveTrailer => Some(PngError::NoTrailer),
+        EditOp::RemoveNamed(_, name) => Some(PngError::MessageNotFound(name.clone())),
+        EditOp::ReplaceImageData(_) => Some(PngError::ChunkNotFound("IDAT".to_string())),
+        EditOp::Remove(t)
+        | EditOp::Replace(t, _)
+        | EditOp::RemovePayload(t)
+        | EditOp::RemoveAll(t) => Some(PngError::ChunkNotFound(t.to_string())),
+    });
+    if let Some(error) = not_found {
+        writer.finish()?;
+        return Err(error);
+    }
+    writer.finish()
+}
+
+/// Apply `ops` to the PNG file `input` and write the result to `output`,
+/// which may be `input` itself to edit in place.
+///
+/// The result is first written to a temporary file in the directory of
+/// `output`, then renamed over it : `output` is either left untouched or
+/// completely replaced, even if an error occurs. The temporary file gets the
+/// permissions of `input`, and its modification time too if `keep_mtime`.
+pub fn edit_file<P, Q>(input: P, output: Q, ops: &[EditOp], keep_mtime: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let source = File::open(input.as_ref())?;
+    let metadata = source.metadata()?;
+    let mtime = if keep_mtime { Some(metadata.modified()?) } else { None };
+    persist_edited(BufReader::new(source), output.as_ref(), ops, metadata.permissions(), mtime)
+}
+
+/// Apply `ops` to the PNG read from `reader`, stdin for example, and write
+/// the result to the file `output`.
+///
+/// Like [`edit_file`], `output` is only replaced once the edit succeeded. It
+/// keeps its permissions if it exists, a new file is readable by everyone.
+pub fn edit_to_file<R, Q>(reader: R, output: Q, ops: &[EditOp]) -> Result<()>
+where
+    R: Read,
+    Q: AsRef<Path>,
+{
+    let output = output.as_ref();
+    let permissions = match std::fs::metadata(output) {
+        Ok(metadata) => metadata.permissions(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => new_file_permissions(output)?,
+        Err(e) => return Err(e.into()),
+    };
+    persist_edited(reader, output, ops, permissions, None)
+}
+
+/// Edit into a temporary file next to `output`, then rename it over `output`
+fn persist_edited<R: Read>(
+    reader: R,
+    output: &Path,
+    ops: &[EditOp],
+    permissions: Permissions,
+    mtime: Option<SystemTime>,
+) -> Result<()> {
+    let temp = tempfile::Builder::new()
+        .prefix(".pngme")
+        .suffix(".png")
+        .tempfile_in(output_dir(output))?;
+
+    let writer = edit(reader, BufWriter::new(temp), ops)?;
+    let temp = writer.into_inner().map_err(|e| e.into_error())?;
+    let file = temp.as_file();
+    file.set_permissions(permissions)?;
+    if let Some(mtime) = mtime {
+        file.set_modified(mtime)?;
+    }
+    file.sync_all()?;
+    temp.persist(output).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn output_dir(output: &Path) -> &Path {
+    match output.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    }
+}
+
+/// Permissions of a file created in the directory of `output` : temporary
+/// files are only readable by their owner
+fn new_file_permissions(output: &Path) -> Result<Permissions> {
+    let mut permissions = std::fs::metadata(output_dir(output))?.permissions();
+    #[cfg(unix)]
+    std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o644);
+    #[cfg(not(unix))]
+    permissions.set_readonly(false);
+    Ok(permissions)
+}
+
+fn write_inserts<W: Write>(writer: &mut PngWriter<W>, ops: &[EditOp]) -> Result<()> {
+    for op in ops {
+        if let EditOp::Insert(chunk) = op {
+            writer.write_chunk(chunk)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("ruSt", "first"),
+            chunk("IDAT", "pixels"),
+            chunk("ruSt", "second"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    fn types(bytes: &[u8]) -> Vec<String> {
+        Png::try_from(bytes)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_no_ops_is_identity() {
+        let input = testing_png().as_bytes();
+        let output = edit(input.as_slice(), Vec::new(), &[]).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_insert_before_iend() {
+        let input = testing_png().as_bytes();
+        let output = edit(
+            input.as_slice(),
+            Vec::new(),
+            &[EditOp::Insert(chunk("teSt", "new"))],
+        )
+        .unwrap();
+
+        let mut expected = testing_png();
+        expected.append_chunk(chunk("teSt", "new"));
+        assert_eq!(output, expected.as_bytes());
+        assert_eq!(types(&output), ["IHDR", "ruSt", "IDAT", "ruSt", "teSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_remove_first() {
+        let input = testing_png().as_bytes();
+        let rust_type = ChunkType::from_str("ruSt").unwrap();
+        let output = edit(input.as_slice(), Vec::new(), &[EditOp::Remove(rust_type)]).unwrap();
+
+        let mut expected = testing_png();
+        expected.remove_chunk("ruSt").unwrap();
+        assert_eq!(output, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_replace() {
+        let input = testing_png().as_bytes();
+        let rust_type = ChunkType::from_str("ruSt").unwrap();
+        let output = edit(
+            input.as_slice(),
+            Vec::new(),
+            &[EditOp::Replace(rust_type, chunk("ruSt", "third"))],
+        )
+        .unwrap();
+
+        let png = Png::try_from(output.as_slice()).unwrap();
+        assert_eq!(png.chunks()[1], chunk("ruSt", "third"));
+        assert_eq!(png.chunks()[3], chunk("ruSt", "second"));
+    }
+
+    #[test]
+    fn test_remove_not_found() {
+        let input = testing_png().as_bytes();
+        let other = ChunkType::from_str("otHr").unwrap();
+        let result = edit(input.as_slice(), Vec::new(), &[EditOp::Remove(other)]);
+        assert!(matches!(result, Err(PngError::ChunkNotFound(t)) if t == "otHr"));
+    }
+
+    #[test]
+    fn test_untouched_chunks_copied_as_is() {
+        // a bad CRC in IDAT is copied, not checked
+        let mut input = testing_png().as_bytes();
+        let idat_crc = 8 + (12 + 6) + (12 + 5) + 12 + 6 - 1;
+        input[idat_crc] ^= 0xff;
+        let output = edit(input.as_slice(), Vec::new(), &[]).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_edit_file_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        std::fs::write(&path, testing_png().as_bytes()).unwrap();
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap()
+            - std::time::Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        edit_file(&path, &path, &[EditOp::Insert(chunk("teSt", "new"))], true).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert!(png.chunk_by_type("teSt").is_some());
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_edit_file_error_keeps_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        let input = testing_png().as_bytes();
+        std::fs::write(&path, &input).unwrap();
+
+        let other = ChunkType::from_str("otHr").unwrap();
+        let result = edit_file(&path, &path, &[EditOp::Remove(other)], false);
+
+        assert!(matches!(result, Err(PngError::ChunkNotFound(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), input);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_edit_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        let input = testing_png().as_bytes();
+
+        let other = ChunkType::from_str("otHr").unwrap();
+        let result = edit_to_file(input.as_slice(), &path, &[EditOp::Remove(other)]);
+        assert!(matches!(result, Err(PngError::ChunkNotFound(_))));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let ops = [EditOp::Insert(chunk("teSt", "new"))];
+        edit_to_file(input.as_slice(), &path, &ops).unwrap();
+        let png = Png::from_file(&path).unwrap();
+        assert!(png.chunk_by_type("teSt").is_some());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o644);
+        }
+    }
+
+    #[test]
+    fn test_remove_split_payload() {
+        let pieces = crate::split::split(&[7u8; 100], 60, None).unwrap();
+        let other = crate::split::split(&[8u8; 100], 60, None).unwrap();
+        let mut png = testing_png();
+        for piece in pieces.iter().chain(&other) {
+            png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), piece.clone()));
+        }
+        let input = png.as_bytes();
+        let rust_type = ChunkType::from_str("ruSt").unwrap();
+
+        // the first ruSt chunk is not a piece : only it is removed
+        let output = edit(
+            input.as_slice(),
+            Vec::new(),
+            &[EditOp::RemovePayload(rust_type.clone())],
+        )
+        .unwrap();
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(types(&output).len(), 5 + 6 - 1);
+
+        let output = edit(
+            output.as_slice(),
+            Vec::new(),
+            &[EditOp::RemovePayload(rust_type.clone())],
+        )
+        .unwrap();
+        let output = edit(output.as_slice(), Vec::new(), &[EditOp::RemovePayload(rust_type)])
+            .unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        let rust_chunks: Vec<&[u8]> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == "ruSt")
+            .map(|c| c.data())
+            .collect();
+        // the first payload is gone, the pieces of the second one are left
+        assert_eq!(rust_chunks.len(), 3);
+        assert_eq!(crate::split::reassemble(rust_chunks).unwrap(), [8u8; 100]);
+    }
+
+    #[test]
+    fn test_remove_named_and_all() {
+        let rust_type = ChunkType::from_str("ruSt").unwrap();
+        let mut png = testing_png();
+        let named = crate::split::split(&[7u8; 100], 60, Some("keys")).unwrap();
+        let other = crate::split::split(b"other", 60, Some("notes")).unwrap();
+        for piece in named.iter().chain(&other) {
+            png.append_chunk(Chunk::new(rust_type.clone(), piece.clone()));
+        }
+        let input = png.as_bytes();
+
+        let op = EditOp::RemoveNamed(rust_type.clone(), "keys".to_string());
+        let output = edit(input.as_slice(), Vec::new(), &[op]).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        let names: Vec<Option<String>> = crate::messages::messages(&png, Some(&rust_type))
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(names, [None, None, Some("notes".to_string())]);
+
+        let op = EditOp::RemoveNamed(rust_type.clone(), "keys".to_string());
+        let result = edit(output.as_slice(), Vec::new(), &[op]);
+        assert!(matches!(result, Err(PngError::MessageNotFound(n)) if n == "keys"));
+
+        let output = edit(input.as_slice(), Vec::new(), &[EditOp::RemoveAll(rust_type.clone())])
+            .unwrap();
+        assert!(!types(&output).contains(&"ruSt".to_string()));
+        let result = edit(output.as_slice(), Vec::new(), &[EditOp::RemoveAll(rust_type)]);
+        assert!(matches!(result, Err(PngError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_corrupted_piece() {
+        let rust_type = ChunkType::from_str("ruSt").unwrap();
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("ruSt", "PNGMS\x09 unknown version"),
+            chunk("IEND", ""),
+        ]);
+        let input = png.as_bytes();
+        let named = EditOp::RemoveNamed(rust_type.clone(), "keys".to_string());
+        for op in [named, EditOp::RemovePayload(rust_type)] {
+            let result = edit(input.as_slice(), Vec::new(), &[op]);
+            assert!(matches!(result, Err(PngError::InvalidPayload(_))));
+        }
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = testing_png();
+        png.append_chunk(chunk("IDAT", "more pixels"));
+        let input = png.as_bytes();
+        let op = EditOp::ReplaceImageData(vec![chunk("IDAT", "new"), chunk("IDAT", "pixels")]);
+        let output = edit(input.as_slice(), Vec::new(), &[op]).unwrap();
+        assert_eq!(types(&output), ["IHDR", "ruSt", "IDAT", "IDAT", "ruSt", "IEND"]);
+        let png = Png::try_from(output.as_slice()).unwrap();
+        let data: Vec<&[u8]> = png.chunks_by_type("IDAT").map(|c| c.data()).collect();
+        assert_eq!(data, [&b"new"[..], b"pixels"]);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut png = testing_png();
+        png.set_trailer(b"trailer".to_vec());
+        let input = png.as_bytes();
+        let insert = EditOp::Insert(chunk("teSt", "new"));
+        let output = edit(input.as_slice(), Vec::new(), &[insert]).unwrap();
+        assert_eq!(Png::try_from(output.as_slice()).unwrap().trailer(), b"trailer");
+
+        let op = EditOp::SetTrailer(b"other".to_vec());
+        let output = edit(input.as_slice(), Vec::new(), &[op]).unwrap();
+        assert_eq!(output[..output.len() - 5], input[..input.len() - 7]);
+        assert_eq!(output[output.len() - 5..], *b"other");
+
+        let output = edit(input.as_slice(), Vec::new(), &[EditOp::RemoveTrailer]).unwrap();
+        assert_eq!(output, testing_png().as_bytes());
+        let result = edit(output.as_slice(), Vec::new(), &[EditOp::RemoveTrailer]);
+        assert!(matches!(result, Err(PngError::NoTrailer)));
+    }
+}
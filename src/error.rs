@@ -0,0 +1,134 @@
+use thiserror::Error;
+
+/// Errors returned when parsing or editing a PNG.
+/// Offsets are byte offsets from the start of the parsed input.
+#[derive(Debug, Error)]
+pub enum PngError {
+    #[error("no valid PNG signature in input bytes")]
+    BadSignature,
+
+    #[error("truncated chunk at offset {offset}")]
+    TruncatedChunk { offset: usize },
+
+    #[error("chunk at offset {offset} declares a length of {length} bytes, above the 2^31-1 limit")]
+    ChunkTooLong { offset: usize, length: u32 },
+
+    #[error("chunk at offset {offset} declares {declared} bytes of data but has {actual}")]
+    LengthMismatch {
+        offset: usize,
+        declared: u32,
+        actual: usize,
+    },
+
+    #[error("CRC error in chunk {chunk_type} at offset {offset} : expected {expected:#010x}, found {actual:#010x}")]
+    CrcMismatch {
+        chunk_type: String,
+        offset: usize,
+        expected: u32,
+        actual: u32,
+    },
+
+    #[error("invalid chunk type {bytes:?} at offset {offset} : must be 4 bytes in A-Z or a-z")]
+    InvalidChunkType { bytes: Vec<u8>, offset: usize },
+
+    #[error("invalid IHDR chunk : {0}")]
+    InvalidIhdr(String),
+
+    #[error("invalid image data : {0}")]
+    InvalidImageData(String),
+
+    #[error("unsupported image : {0}")]
+    UnsupportedImage(String),
+
+    #[error("chunk type {0} not found in this png")]
+    ChunkNotFound(String),
+
+    #[error("no message named {0} in this png")]
+    MessageNotFound(String),
+
+    #[error("no data after IEND in this png")]
+    NoTrailer,
+
+    #[error("message authentication failed : wrong passphrase or key, or tampered data")]
+    AuthenticationFailed,
+
+    #[error("signature verification failed : wrong public key, or the image or its metadata changed")]
+    SignatureMismatch,
+
+    #[error("payload larger than the limit of {limit} bytes")]
+    PayloadTooLarge { limit: u64 },
+
+    #[error("invalid payload : {0}")]
+    InvalidPayload(String),
+
+    #[error("chunk data is not valid UTF-8")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl PngError {
+    /// Short identifier of the error, stable for scripts, e.g. `crc-mismatch`
+    pub fn code(&self) -> &'static str {
+        match self {
+            PngError::BadSignature => "bad-signature",
+            PngError::TruncatedChunk { .. } => "truncated-chunk",
+            PngError::ChunkTooLong { .. } => "chunk-too-long",
+            PngError::LengthMismatch { .. } => "length-mismatch",
+            PngError::CrcMismatch { .. } => "crc-mismatch",
+            PngError::InvalidChunkType { .. } => "invalid-chunk-type",
+            PngError::InvalidIhdr(_) => "invalid-ihdr",
+            PngError::InvalidImageData(_) => "invalid-image-data",
+            PngError::UnsupportedImage(_) => "unsupported-image",
+            PngError::ChunkNotFound(_) => "chunk-not-found",
+            PngError::MessageNotFound(_) => "message-not-found",
+            PngError::NoTrailer => "no-trailer",
+            PngError::AuthenticationFailed => "authentication-failed",
+            PngError::SignatureMismatch => "signature-mismatch",
+            PngError::PayloadTooLarge { .. } => "payload-too-large",
+            PngError::InvalidPayload(_) => "invalid-payload",
+            PngError::InvalidUtf8(_) => "invalid-utf8",
+            PngError::Io(_) => "io",
+        }
+    }
+
+    /// Shift the offset carried by the error by `base` bytes, used when a chunk
+    /// parsed on its own is part of a larger input
+    pub(crate) fn at_offset(self, base: usize) -> Self {
+        match self {
+            PngError::TruncatedChunk { offset } => PngError::TruncatedChunk {
+                offset: offset + base,
+            },
+            PngError::ChunkTooLong { offset, length } => PngError::ChunkTooLong {
+                offset: offset + base,
+                length,
+            },
+            PngError::LengthMismatch {
+                offset,
+                declared,
+                actual,
+            } => PngError::LengthMismatch {
+                offset: offset + base,
+                declared,
+                actual,
+            },
+            PngError::CrcMismatch {
+                chunk_type,
+                offset,
+                expected,
+                actual,
+            } => PngError::CrcMismatch {
+                chunk_type,
+                offset: offset + base,
+                expected,
+                actual,
+            },
+            PngError::InvalidChunkType { bytes, offset } => PngError::InvalidChunkType {
+                bytes,
+                offset: offset + base,
+            },
+            e => e,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, Result};
+use flate2::{Decompress, FlushDecompress, Status};
+
+/// Largest IDAT chunk written when the image had none to copy the size of
+pub const DEFAULT_CHUNK_LEN: usize = 64 * 1024;
+
+/// The image data : the data of every IDAT chunk of `png`, one after the
+/// other. Returns [`PngError::ChunkNotFound`] if it is empty.
+pub fn concat(png: &Png) -> Result<Vec<u8>> {
+    let data: Vec<u8> = png
+        .chunks_by_type("IDAT")
+        .flat_map(|c| c.data().iter().copied())
+        .collect();
+    if data.is_empty() {
+        return Err(PngError::ChunkNotFound("IDAT".to_string()));
+    }
+    Ok(data)
+}
+
+/// Size of the largest IDAT chunk of `png`, the size new ones are cut to
+pub fn chunk_len(png: &Png) -> usize {
+    png.chunks_by_type("IDAT")
+        .map(|c| c.data().len())
+        .max()
+        .unwrap_or(DEFAULT_CHUNK_LEN)
+}
+
+/// Cut the image data into IDAT chunks of at most `max_chunk_len` bytes.
+/// Returns [`PngError::ChunkTooLong`] if a chunk would be longer than
+/// [`Chunk::MAX_LENGTH`].
+pub fn rechunk(data: &[u8], max_chunk_len: usize) -> Result<Vec<Chunk>> {
+    let idat = ChunkType::try_from(*b"IDAT")?;
+    data.chunks(max_chunk_len.max(1))
+        .map(|data| Chunk::try_new(idat.clone(), data.to_vec()))
+        .collect()
+}
+
+/// A copy of `png` holding the image data `data` : its IDAT chunks are
+/// replaced by chunks of the size of the largest one, written where the first
+/// one was. The other chunks and the trailer are kept.
+pub fn replace(png: &Png, data: &[u8]) -> Result<Png> {
+    let mut idat = Some(rechunk(data, chunk_len(png))?);
+    let mut chunks = Vec::with_capacity(png.chunks().len());
+    for chunk in png.chunks() {
+        if chunk.chunk_type().bytes() != *b"IDAT" {
+            chunks.push(chunk.clone());
+        } else if let Some(new) = idat.take() {
+            chunks.extend(new);
+        }
+    }
+    if idat.is_some() {
+        return Err(PngError::ChunkNotFound("IDAT".to_string()));
+    }
+    let mut replaced = Png::from_chunks(chunks);
+    replaced.set_trailer(png.trailer().to_vec());
+    Ok(replaced)
+}
+
+/// Length of the zlib stream starting `data`, up to its Adler-32 checksum.
+/// Decoders ignore what follows. Returns [`PngError::InvalidImageData`] if
+/// the stream is corrupted or doesn't end.
+pub fn stream_len(data: &[u8]) -> Result<usize> {
+    let mut inflater = Decompress::new(true);
+    let mut output = vec![0u8; 64 * 1024];
+    loop {
+        let consumed = inflater.total_in() as usize;
+        match inflater.decompress(&data[consumed..], &mut output, FlushDecompress::None) {
+            Ok(Status::StreamEnd) => return Ok(inflater.total_in() as usize),
+            Ok(_) if inflater.total_in() as usize == data.len() => {
+                return Err(PngError::InvalidImageData(
+                    "the zlib stream doesn't end".to_string(),
+                ))
+            }
+            Ok(_) => (),
+            Err(e) => {
+                return Err(PngError::InvalidImageData(format!(
+                    "corrupted zlib stream : {}",
+                    e
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::testing_png;
+
+    #[test]
+    fn test_concat_and_rechunk() {
+        let png = testing_png(9, 7, 2, false);
+        let data = concat(&png).unwrap();
+        let sizes: Vec<usize> = png.chunks_by_type("IDAT").map(|c| c.data().len()).collect();
+        assert_eq!(data.len(), sizes.iter().sum::<usize>());
+        assert_eq!(chunk_len(&png), *sizes.iter().max().unwrap());
+
+        let chunks = rechunk(&data, 10).unwrap();
+        assert_eq!(chunks.len(), data.len().div_ceil(10));
+        assert!(chunks.iter().all(|c| c.data().len() <= 10));
+        let rejoined: Vec<u8> = chunks.iter().flat_map(|c| c.data().to_vec()).collect();
+        assert_eq!(rejoined, data);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut png = testing_png(9, 7, 2, false);
+        png.set_trailer(b"trailer".to_vec());
+        let data = vec![7u8; 2 * chunk_len(&png) + 1];
+        let replaced = replace(&png, &data).unwrap();
+        let types: Vec<String> =
+            replaced.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "IDAT", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(concat(&replaced).unwrap(), data);
+        assert_eq!(replaced.trailer(), b"trailer");
+
+        let mut chunks = png.chunks().to_vec();
+        chunks.retain(|c| c.chunk_type().to_string() != "IDAT");
+        let png = Png::from_chunks(chunks);
+        assert!(matches!(concat(&png), Err(PngError::ChunkNotFound(_))));
+        assert!(matches!(replace(&png, &data), Err(PngError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_stream_len() {
+        let png = testing_png(9, 7, 2, false);
+        let mut data = concat(&png).unwrap();
+        let len = data.len();
+        assert_eq!(stream_len(&data).unwrap(), len);
+        data.extend_from_slice(b"slack");
+        assert_eq!(stream_len(&data).unwrap(), len);
+        assert!(matches!(
+            stream_len(&data[..len - 5]),
+            Err(PngError::InvalidImageData(_))
+        ));
+        assert!(stream_len(b"not zlib").is_err());
+    }
+}
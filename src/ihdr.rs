@@ -0,0 +1,259 @@
+use crate::chunk::Chunk;
+use crate::{PngError, Result};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Colour type of the image, from the IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// Number of samples per pixel
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths allowed by the spec for this colour type
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(invalid(format!("unknown colour type {}", value))),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale + alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The image header, data of the IHDR chunk.
+/// See http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    compression_method: u8,
+    filter_method: u8,
+    interlaced: bool,
+}
+
+impl Ihdr {
+    /// Length of the IHDR data
+    pub const LENGTH: usize = 13;
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn compression_method(&self) -> u8 {
+        self.compression_method
+    }
+
+    pub fn filter_method(&self) -> u8 {
+        self.filter_method
+    }
+
+    /// Adam7 interlacing, the only interlace method of the spec
+    pub fn interlaced(&self) -> bool {
+        self.interlaced
+    }
+
+    /// Number of bits of one pixel
+    pub fn bits_per_pixel(&self) -> u32 {
+        self.color_type.channels() as u32 * self.bit_depth as u32
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = PngError;
+
+    /// Parse and validate the 13 bytes of IHDR data
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != Ihdr::LENGTH {
+            return Err(invalid(format!(
+                "length is {} bytes instead of {}",
+                data.len(),
+                Ihdr::LENGTH
+            )));
+        }
+        let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        for (name, value) in [("width", width), ("height", height)] {
+            if value == 0 || value > Chunk::MAX_LENGTH {
+                return Err(invalid(format!("{} {} not in 1..2^31-1", name, value)));
+            }
+        }
+        let bit_depth = data[8];
+        let color_type = ColorType::try_from(data[9])?;
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(invalid(format!(
+                "bit depth {} not allowed for colour type {}",
+                bit_depth, color_type
+            )));
+        }
+        if data[10] != 0 {
+            return Err(invalid(format!("unknown compression method {}", data[10])));
+        }
+        if data[11] != 0 {
+            return Err(invalid(format!("unknown filter method {}", data[11])));
+        }
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            m => return Err(invalid(format!("unknown interlace method {}", m))),
+        };
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlaced,
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().bytes() != *b"IHDR" {
+            return Err(invalid(format!("chunk {} is not IHDR", chunk.chunk_type())));
+        }
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{}, {} bits, {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            if self.interlaced {
+                "interlaced"
+            } else {
+                "not interlaced"
+            }
+        )
+    }
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidIhdr(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr_data(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+        width
+            .to_be_bytes()
+            .iter()
+            .chain(height.to_be_bytes().iter())
+            .chain([bit_depth, color_type, 0, 0, 0].iter())
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_ihdr() {
+        let ihdr = Ihdr::try_from(ihdr_data(50, 40, 8, 6).as_slice()).unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 40);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+        assert!(!ihdr.interlaced());
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn test_bit_depth_color_type_combinations() {
+        let valid = [(0, 1), (0, 16), (2, 8), (2, 16), (3, 1), (3, 8), (4, 16), (6, 8)];
+        for (color_type, bit_depth) in valid {
+            assert!(Ihdr::try_from(ihdr_data(1, 1, bit_depth, color_type).as_slice()).is_ok());
+        }
+        let invalid = [(0, 3), (2, 4), (3, 16), (4, 1), (6, 2), (1, 8), (5, 8), (7, 8)];
+        for (color_type, bit_depth) in invalid {
+            let ihdr = Ihdr::try_from(ihdr_data(1, 1, bit_depth, color_type).as_slice());
+            assert!(matches!(ihdr, Err(PngError::InvalidIhdr(_))));
+        }
+    }
+
+    #[test]
+    fn test_invalid_dimensions() {
+        assert!(Ihdr::try_from(ihdr_data(0, 1, 8, 2).as_slice()).is_err());
+        assert!(Ihdr::try_from(ihdr_data(1, 1 << 31, 8, 2).as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_methods() {
+        for index in [10, 11, 12] {
+            let mut data = ihdr_data(1, 1, 8, 2);
+            data[index] = 2;
+            assert!(Ihdr::try_from(data.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        let data = ihdr_data(1, 1, 8, 2);
+        assert!(Ihdr::try_from(&data[..12]).is_err());
+    }
+}
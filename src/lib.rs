@@ -0,0 +1,64 @@
+//! pngme : read, inspect and edit the chunks of a PNG file.
+//!
+//! The library exposes the three building blocks used by the `pngme` binary :
+//! [`ChunkType`], [`Chunk`] and [`Png`]. A `Png` can be parsed from bytes or
+//! from a file, its chunks inspected, added or removed, and serialized back
+//! with the trailer found after `IEND`.
+//! [`PngReader`] and [`PngWriter`] read and write the chunks of a large file
+//! one at a time, and [`edit`] adds or removes chunks while streaming from one
+//! to the other. [`payload`] describes what is hidden, a message or a file,
+//! [`crypto`] seals it with a passphrase or public keys before it is hidden in
+//! a chunk, [`messages`] lists and reads the named messages of an image, and
+//! [`signature`] signs the image and its messages. [`pixels`] decodes the
+//! image data, in which [`lsb`] hides bytes instead of a chunk. [`idat`] joins
+//! and cuts again the IDAT chunks, after whose zlib stream [`slack`] hides
+//! bytes without changing the pixels, [`trailer`] frames the bytes hidden
+//! after `IEND`, and [`scan`] looks for the signs of data hidden by pngme or
+//! other tools.
+//!
+//! ```no_run
+//! use pngme::{Chunk, ChunkType, Png};
+//! use std::str::FromStr;
+//!
+//! let mut png = Png::from_file("file.png")?;
+//! let chunk_type = ChunkType::from_str("ruSt")?;
+//! png.append_chunk(Chunk::new(chunk_type, b"hidden message".to_vec()));
+//! std::fs::write("out.png", png.as_bytes())?;
+//! # Ok::<(), pngme::Error>(())
+//! ```
+
+pub mod check;
+pub mod chunk;
+pub mod chunk_type;
+pub mod crypto;
+pub mod edit;
+pub mod error;
+pub mod idat;
+pub mod ihdr;
+pub mod lsb;
+pub mod messages;
+pub mod payload;
+pub mod pixels;
+pub mod png;
+pub mod reader;
+pub mod scan;
+pub mod signature;
+pub mod slack;
+pub mod split;
+pub mod summary;
+pub mod trailer;
+pub mod writer;
+
+pub use check::{check, Finding, Severity};
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use edit::{edit, edit_file, edit_to_file, EditOp};
+pub use error::PngError;
+pub use ihdr::{ColorType, Ihdr};
+pub use png::Png;
+pub use reader::PngReader;
+pub use summary::ChunkSummary;
+pub use writer::PngWriter;
+
+pub type Error = PngError;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,338 @@
+use crate::ihdr::ColorType;
+use crate::pixels::{Pass, Pixels};
+use crate::png::Png;
+use crate::{PngError, Result};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// First bytes of the data hidden in the pixels
+pub const MAGIC: [u8; 5] = *b"PNGML";
+/// Version of the layout of the hidden data
+pub const VERSION: u8 = 1;
+/// magic, version, length (u32)
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+/// Most low bits of a sample which may carry data
+pub const MAX_BITS: u8 = 4;
+/// Low bits of a sample used when no count is given
+pub const DEFAULT_BITS: u8 = 1;
+/// Prefix of the key seeding the walk, so that it is not the key of anything else
+const DOMAIN: &[u8] = b"pngme lsb walk v1\0";
+
+/// Hide `data` in the `bits` least significant bits of the colour samples of
+/// `png` and return the new image, the other chunks being kept.
+///
+/// The bits go to the samples in the order of the scanlines, or in an order
+/// drawn from `key` if given, which is then needed to find them with the same
+/// `bits`. The alpha channel is left untouched. The data is stored as :
+///
+/// ```text
+/// "PNGML" | version | length (u32) | data
+/// ```
+///
+/// Returns [`PngError::PayloadTooLarge`] if `data` is above the
+/// [`capacity`] of the image.
+pub fn embed(png: &Png, data: &[u8], key: Option<&str>, bits: u8) -> Result<Png> {
+    let mut pixels = Pixels::from_png(png)?;
+    let carriers = Carriers::new(&pixels, bits)?;
+    let limit = carriers.capacity();
+    if data.len() > limit {
+        return Err(PngError::PayloadTooLarge { limit: limit as u64 });
+    }
+    let mut framed = Vec::with_capacity(HEADER_LEN + data.len());
+    framed.extend_from_slice(&MAGIC);
+    framed.push(VERSION);
+    framed.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    framed.extend_from_slice(data);
+
+    let mut walk = Walk::new(carriers.total, key);
+    let samples = pixels.data_mut();
+    for byte in framed {
+        for bit in (0..8).rev() {
+            let (offset, shift) = carriers.position(walk.next().expect("capacity checked"));
+            samples[offset] = (samples[offset] & !(1 << shift)) | (((byte >> bit) & 1) << shift);
+        }
+    }
+    pixels.replace_in(png)
+}
+
+/// The data hidden in the pixels of `png` by [`embed`] with the same `key`
+/// and `bits`.
+///
+/// Returns [`PngError::InvalidPayload`] if nothing was hidden this way, or
+/// with another key.
+pub fn extract(png: &Png, key: Option<&str>, bits: u8) -> Result<Vec<u8>> {
+    let pixels = Pixels::from_png(png)?;
+    let carriers = Carriers::new(&pixels, bits)?;
+    if carriers.total < HEADER_LEN * 8 {
+        return Err(not_found());
+    }
+    let mut walk = Walk::new(carriers.total, key);
+    let samples = pixels.data();
+    let mut read = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                (0..8).fold(0u8, |byte, _| {
+                    let (offset, shift) = carriers.position(walk.next().expect("length checked"));
+                    (byte << 1) | ((samples[offset] >> shift) & 1)
+                })
+            })
+            .collect()
+    };
+    let header = read(HEADER_LEN);
+    if header[..MAGIC.len()] != MAGIC {
+        return Err(not_found());
+    }
+    if header[MAGIC.len()] != VERSION {
+        let version = header[MAGIC.len()];
+        return Err(PngError::InvalidPayload(format!("unsupported LSB version {}", version)));
+    }
+    let len = u32::from_be_bytes(header[MAGIC.len() + 1..].try_into().unwrap()) as usize;
+    if len > carriers.capacity() {
+        return Err(PngError::InvalidPayload(format!(
+            "{} bytes hidden in pixels holding at most {}",
+            len,
+            carriers.capacity()
+        )));
+    }
+    Ok(read(len))
+}
+
+/// Bytes which can be hidden in the pixels of `png` by [`embed`] with `bits`
+/// bits per sample
+pub fn capacity(png: &Png, bits: u8) -> Result<usize> {
+    Ok(Carriers::new(&Pixels::from_png(png)?, bits)?.capacity())
+}
+
+/// The last byte of each colour sample of `pixels` in the order of the
+/// scanlines, one list per channel : the bytes whose low bits [`embed`] changes
+pub(crate) fn color_samples(pixels: &Pixels) -> Result<Vec<Vec<u8>>> {
+    let carriers = Carriers::new(pixels, 1)?;
+    let mut channels = vec![Vec::with_capacity(carriers.total / carriers.colors); carriers.colors];
+    for index in 0..carriers.total {
+        let (offset, _) = carriers.position(index);
+        channels[index % carriers.colors].push(pixels.data()[offset]);
+    }
+    Ok(channels)
+}
+
+/// The bits of the pixel data which carry the hidden data : the `bits` lowest
+/// bits of the last byte of each colour sample
+struct Carriers {
+    /// Index of the first carrier of each pass, and the pass
+    passes: Vec<(usize, Pass)>,
+    pixel_len: usize,
+    sample_len: usize,
+    colors: usize,
+    bits: usize,
+    total: usize,
+}
+
+impl Carriers {
+    /// Returns [`PngError::UnsupportedImage`] for indexed images and bit
+    /// depths below 8, where changing the lowest bit would be visible
+    fn new(pixels: &Pixels, bits: u8) -> Result<Carriers> {
+        if !(1..=MAX_BITS).contains(&bits) {
+            return Err(PngError::InvalidPayload(format!(
+                "{} bits per sample, between 1 and {} are allowed",
+                bits, MAX_BITS
+            )));
+        }
+        let ihdr = pixels.ihdr();
+        if ihdr.color_type() == ColorType::Indexed || ihdr.bit_depth() < 8 {
+            return Err(PngError::UnsupportedImage(format!(
+                "hiding data in the pixels needs 8 or 16 bits samples and no palette, not {}",
+                ihdr
+            )));
+        }
+        let colors = match ihdr.color_type() {
+            ColorType::GrayscaleAlpha | ColorType::Rgba => ihdr.color_type().channels() - 1,
+            other => other.channels(),
+        } as usize;
+        let mut total = 0;
+        let passes = pixels
+            .passes()
+            .iter()
+            .map(|pass| {
+                let first = total;
+                total += pass.width as usize * pass.height as usize * colors * bits as usize;
+                (first, *pass)
+            })
+            .collect();
+        let sample_len = ihdr.bit_depth() as usize / 8;
+        Ok(Carriers {
+            passes,
+            pixel_len: sample_len * ihdr.color_type().channels() as usize,
+            sample_len,
+            colors,
+            bits: bits as usize,
+            total,
+        })
+    }
+
+    /// Bytes of data which fit after the header
+    fn capacity(&self) -> usize {
+        (self.total / 8).saturating_sub(HEADER_LEN)
+    }
+
+    /// Offset in the pixel data of the byte holding the carrier `index`, and
+    /// the position of its bit in this byte
+    fn position(&self, index: usize) -> (usize, u8) {
+        let (first, pass) = self
+            .passes
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= index)
+            .expect("the first pass starts at 0");
+        let (index, bit) = ((index - first) / self.bits, (index - first) % self.bits);
+        let per_row = pass.width as usize * self.colors;
+        let (row, column) = (index / per_row, index % per_row);
+        let (pixel, color) = (column / self.colors, column % self.colors);
+        let offset = pass.offset
+            + row * pass.row_len
+            + pixel * self.pixel_len
+            + color * self.sample_len
+            + self.sample_len
+            - 1;
+        (offset, bit as u8)
+    }
+}
+
+/// The order in which the carriers are used : all of them in order, or a
+/// permutation drawn from ChaCha20 keyed by a hash of the key, built lazily
+/// with a Fisher-Yates shuffle which only remembers the swapped positions
+struct Walk {
+    next: usize,
+    total: usize,
+    keyed: Option<(ChaCha20, HashMap<usize, usize>)>,
+}
+
+impl Walk {
+    fn new(total: usize, key: Option<&str>) -> Walk {
+        let keyed = key.map(|key| {
+            let seed = Sha256::new().chain_update(DOMAIN).chain_update(key).finalize();
+            (ChaCha20::new(&seed, &[0u8; 12].into()), HashMap::new())
+        });
+        Walk {
+            next: 0,
+            total,
+            keyed,
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.next >= self.total {
+            return None;
+        }
+        let i = self.next;
+        self.next += 1;
+        let (cipher, swapped) = match &mut self.keyed {
+            None => return Some(i),
+            Some(keyed) => keyed,
+        };
+        let j = i + random_below(cipher, self.total - i);
+        let at_j = swapped.get(&j).copied().unwrap_or(j);
+        let at_i = swapped.get(&i).copied().unwrap_or(i);
+        swapped.insert(j, at_i);
+        swapped.remove(&i);
+        Some(at_j)
+    }
+}
+
+/// Uniform random number in `0..n` from the key stream, without modulo bias
+fn random_below(cipher: &mut ChaCha20, n: usize) -> usize {
+    let n = n as u64;
+    let zone = u64::MAX - u64::MAX % n;
+    loop {
+        let mut bytes = [0u8; 8];
+        cipher.apply_keystream(&mut bytes);
+        let value = u64::from_le_bytes(bytes);
+        if value < zone {
+            return (value % n) as usize;
+        }
+    }
+}
+
+fn not_found() -> PngError {
+    PngError::InvalidPayload("no data hidden in the pixels, or with another key".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::testing_png;
+
+    #[test]
+    fn test_embed_and_extract() {
+        for (color_type, interlaced) in [(2, false), (6, false), (0, true), (4, true)] {
+            let png = testing_png(20, 15, color_type, interlaced);
+            let embedded = embed(&png, b"hidden in the pixels", None, 1).unwrap();
+            assert_eq!(extract(&embedded, None, 1).unwrap(), b"hidden in the pixels");
+            // the image is still valid once serialized and parsed again
+            let bytes = embedded.as_bytes();
+            let parsed = Png::try_from(bytes.as_slice()).unwrap();
+            assert_eq!(extract(&parsed, None, 1).unwrap(), b"hidden in the pixels");
+        }
+    }
+
+    #[test]
+    fn test_only_lowest_bits_change() {
+        let png = testing_png(20, 15, 6, false);
+        let before = Pixels::from_png(&png).unwrap();
+        let after = Pixels::from_png(&embed(&png, &[0xa5; 50], Some("key"), 1).unwrap()).unwrap();
+        let changed = before.data().iter().zip(after.data()).enumerate();
+        for (i, (a, b)) in changed {
+            assert!(a ^ b <= 1, "byte {} changed from {} to {}", i, a, b);
+            if i % 4 == 3 {
+                assert_eq!(a, b, "alpha changed");
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_walk() {
+        let png = testing_png(20, 15, 2, false);
+        let embedded = embed(&png, b"secret", Some("walk key"), 1).unwrap();
+        assert_eq!(extract(&embedded, Some("walk key"), 1).unwrap(), b"secret");
+        assert!(matches!(extract(&embedded, Some("other"), 1), Err(PngError::InvalidPayload(_))));
+        assert!(matches!(extract(&embedded, None, 1), Err(PngError::InvalidPayload(_))));
+
+        let walk: Vec<usize> = Walk::new(100, Some("walk key")).collect();
+        let mut sorted = walk.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        assert_ne!(walk, sorted);
+    }
+
+    #[test]
+    fn test_capacity() {
+        // 20 x 15 RGB pixels : 900 bits
+        let png = testing_png(20, 15, 2, false);
+        assert_eq!(capacity(&png, 1).unwrap(), 900 / 8 - HEADER_LEN);
+        assert_eq!(capacity(&png, 4).unwrap(), 3600 / 8 - HEADER_LEN);
+        let too_large = vec![0u8; capacity(&png, 1).unwrap() + 1];
+        let result = embed(&png, &too_large, None, 1);
+        assert!(matches!(result, Err(PngError::PayloadTooLarge { limit: 102 })));
+        assert!(embed(&png, &too_large[1..], None, 1).is_ok());
+        assert!(embed(&png, &too_large, None, 2).is_ok());
+        assert!(capacity(&png, 0).is_err());
+        assert!(capacity(&png, MAX_BITS + 1).is_err());
+    }
+
+    #[test]
+    fn test_several_bits() {
+        let png = testing_png(20, 15, 2, true);
+        let before = Pixels::from_png(&png).unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        let embedded = embed(&png, &data, Some("key"), 3).unwrap();
+        assert_eq!(extract(&embedded, Some("key"), 3).unwrap(), data);
+        assert!(extract(&embedded, Some("key"), 2).is_err());
+        let after = Pixels::from_png(&embedded).unwrap();
+        assert!(before.data().iter().zip(after.data()).all(|(a, b)| a ^ b < 8));
+    }
+}
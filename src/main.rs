@@ -1,60 +1,397 @@
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
-use args::{PngMeArgs, PnnmeFunctions};
+mod output;
+use anyhow::bail;
+use args::{DecodeArgs, EncodeArgs, Format, Method, PngMeArgs, PnnmeFunctions};
+use commands::{Decoded, Destination, Placement, Seal, Source, Unseal};
 use clap::Parser;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use pngme::crypto::{self, Recipient};
+use pngme::lsb;
+use pngme::payload::Compression;
+use pngme::scan::Verdict;
+use pngme::Severity;
+use serde_json::json;
+use std::process::ExitCode;
+use std::str::FromStr;
 
-//pub type Error = Box<dyn std::error::Error>;
-pub type Error = anyhow::Error;
-pub type Result<T> = std::result::Result<T, Error>;
-
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     env_logger::init();
     let cmd = PngMeArgs::parse();
     info!("arguments : {:?}", cmd);
+    let format = cmd.format;
     match cmd.command_type {
         PnnmeFunctions::Encode(args) => {
-            match commands::encode(
-                &args.file_to_encode,
-                args.type_chunk,
-                &args.message_to_encode,
-            ) {
-                Ok(f) => info!("message encoded in file {}", f.display()),
-                Err(e) => error!("message not encoded, error : {}", e),
+            let destination = Destination {
+                output: args.output.as_deref(),
+                in_place: args.in_place,
+                preserve_mtime: args.preserve_mtime,
+            };
+            let stdout_taken = destination.to_stdout(&args.file_to_encode);
+            let payload = commands::read_payload(
+                args.message_to_encode.as_deref(),
+                args.payload_file.as_deref(),
+            );
+            let result = payload.and_then(|payload| {
+                let placement = placement(&args)?;
+                let seal = seal(&args)?;
+                commands::encode(
+                    &args.file_to_encode,
+                    &placement,
+                    &payload,
+                    if args.compress {
+                        Compression::Deflate
+                    } else {
+                        Compression::None
+                    },
+                    &seal,
+                    &destination,
+                )
+            });
+            match result {
+                Ok(f) => {
+                    info!("message encoded in file {}", f.display());
+                    if format == Format::Json {
+                        let value = json!({"file": args.file_to_encode, "output": f});
+                        output::json(&value, stdout_taken);
+                    }
+                }
+                Err(e) => {
+                    error!("message not encoded, error : {}", e);
+                    output::error(format, &e, stdout_taken);
+                    return output::exit_code(&e);
+                }
             }
         }
         PnnmeFunctions::Decode(args) => {
-            match commands::decode(&args.file_with_message, args.type_chunk) {
-                Ok(s) => info!("decrypted message : {}", s),
-                Err(e) => error!(
-                    "failed to decode message : {} in file {}",
-                    e,
-                    &args.file_with_message.display()
-                ),
+            let result = source(&args).and_then(|source| {
+                let unseal = unseal(&args)?;
+                commands::decode(
+                    &args.file_with_message,
+                    &source,
+                    &unseal,
+                    args.extract_to.as_deref(),
+                    args.max_size,
+                )
+            });
+            match result {
+                Ok(Decoded::Message(s)) => {
+                    info!("decrypted message : {}", s);
+                    if format == Format::Json {
+                        let value = json!({
+                            "file": args.file_with_message,
+                            "chunk_type": args.type_chunk,
+                            "name": args.name,
+                            "message": s,
+                        });
+                        output::json(&value, false);
+                    }
+                }
+                Ok(Decoded::Extracted {
+                    file_name,
+                    path,
+                    size,
+                }) => {
+                    info!("payload of {} bytes extracted to {}", size, path.display());
+                    if format == Format::Json {
+                        let value = json!({
+                            "file": args.file_with_message,
+                            "chunk_type": args.type_chunk,
+                            "name": args.name,
+                            "file_name": file_name,
+                            "extracted_to": path,
+                            "size": size,
+                        });
+                        output::json(&value, false);
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "failed to decode message : {} in file {}",
+                        e,
+                        &args.file_with_message.display()
+                    );
+                    output::error(format, &e, false);
+                    return output::exit_code(&e);
+                }
             }
         }
         PnnmeFunctions::Remove(args) => {
-            match commands::remove(&args.file_to_clean, args.type_chunk) {
-                Ok(f) => info!("file cleaned of the message : {}", f.display()),
-                Err(e) => error!(
-                    "failed to clean the file {} : {}",
-                    &args.file_to_clean.display(),
-                    e
-                ),
+            let destination = Destination {
+                output: args.output.as_deref(),
+                in_place: args.in_place,
+                preserve_mtime: args.preserve_mtime,
+            };
+            let stdout_taken = destination.to_stdout(&args.file_to_clean);
+            match commands::remove(
+                &args.file_to_clean,
+                args.type_chunk,
+                args.name.as_deref(),
+                args.all,
+                &destination,
+            ) {
+                Ok(f) => {
+                    info!("file cleaned of the message : {}", f.display());
+                    if format == Format::Json {
+                        let value = json!({"file": args.file_to_clean, "output": f});
+                        output::json(&value, stdout_taken);
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "failed to clean the file {} : {}",
+                        &args.file_to_clean.display(),
+                        e
+                    );
+                    output::error(format, &e, stdout_taken);
+                    return output::exit_code(&e);
+                }
             }
         }
-        PnnmeFunctions::Print(args) => match commands::print(&args.file_to_print) {
+        PnnmeFunctions::Print(args) => match commands::print(&args.file_to_print, format) {
             Ok(_f) => debug!("file printed"),
-            Err(e) => error!(
-                "error {} when printing file {}",
-                e,
-                &args.file_to_print.display()
-            ),
+            Err(e) => {
+                error!(
+                    "error {} when printing file {}",
+                    e,
+                    &args.file_to_print.display()
+                );
+                output::error(format, &e, false);
+                return output::exit_code(&e);
+            }
+        },
+        PnnmeFunctions::Check(args) => match commands::check(&args.file_to_check, format) {
+            Ok(None) => (),
+            Ok(Some(Severity::Warning)) => return ExitCode::from(output::EXIT_CHECK_WARNINGS),
+            Ok(Some(Severity::Error)) => return ExitCode::from(output::EXIT_INVALID_PNG),
+            Err(e) => {
+                error!(
+                    "error {} when checking file {}",
+                    e,
+                    &args.file_to_check.display()
+                );
+                output::error(format, &e, false);
+                return output::exit_code(&e);
+            }
+        },
+        PnnmeFunctions::Keygen(args) => {
+            match commands::keygen(args.output.as_deref(), args.signing) {
+                Ok(public_key) => {
+                    if format == Format::Json {
+                        let value = json!({"output": args.output, "public_key": public_key});
+                        output::json(&value, args.output.is_none());
+                    } else {
+                        eprintln!("public key : {}", public_key);
+                    }
+                }
+                Err(e) => {
+                    error!("error {} when generating a key", e);
+                    output::error(format, &e, args.output.is_none());
+                    return output::exit_code(&e);
+                }
+            }
+        }
+        PnnmeFunctions::Sign(args) => {
+            let destination = Destination {
+                output: args.output.as_deref(),
+                in_place: args.in_place,
+                preserve_mtime: args.preserve_mtime,
+            };
+            let stdout_taken = destination.to_stdout(&args.file_to_sign);
+            match commands::sign(&args.file_to_sign, &args.key, &args.include, &destination) {
+                Ok(f) => {
+                    info!("file signed : {}", f.display());
+                    if format == Format::Json {
+                        let value = json!({"file": args.file_to_sign, "output": f});
+                        output::json(&value, stdout_taken);
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "failed to sign the file {} : {}",
+                        &args.file_to_sign.display(),
+                        e
+                    );
+                    output::error(format, &e, stdout_taken);
+                    return output::exit_code(&e);
+                }
+            }
+        }
+        PnnmeFunctions::Verify(args) => {
+            match commands::verify(&args.file_to_verify, &args.pubkey) {
+                Ok(signed) => {
+                    let ancillary: Vec<String> =
+                        signed.ancillary.iter().map(|t| t.to_string()).collect();
+                    if format == Format::Json {
+                        let value = json!({
+                            "file": args.file_to_verify,
+                            "valid": true,
+                            "signer": signed.signer.to_string(),
+                            "ancillary": ancillary,
+                            "covered": signed.covered,
+                            "unsigned_trailer": signed.unsigned_trailer,
+                        });
+                        output::json(&value, false);
+                    } else {
+                        println!(
+                            "OK : signed by {}, {} chunks covered (critical chunks{})",
+                            signed.signer,
+                            signed.covered,
+                            ancillary.iter().map(|t| format!(", {}", t)).collect::<String>()
+                        );
+                        if signed.unsigned_trailer > 0 {
+                            warn!(
+                                "the {} bytes after IEND are not covered by the signature",
+                                signed.unsigned_trailer
+                            );
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "error {} when verifying file {}",
+                        e,
+                        &args.file_to_verify.display()
+                    );
+                    output::error(format, &e, false);
+                    return output::exit_code(&e);
+                }
+            }
+        }
+        PnnmeFunctions::ListMessages(args) => {
+            match commands::list_messages(&args.file, args.type_chunk.as_deref(), format) {
+                Ok(()) => debug!("messages listed"),
+                Err(e) => {
+                    error!("error {} when listing the messages of {}", e, &args.file.display());
+                    output::error(format, &e, false);
+                    return output::exit_code(&e);
+                }
+            }
+        }
+        PnnmeFunctions::Capacity(args) => {
+            let seal_overhead = match args.recipients {
+                _ if args.encrypt => crypto::PASSPHRASE_OVERHEAD,
+                Some(count) => crypto::recipients_overhead(count),
+                None => 0,
+            };
+            let payload_file = args.payload_file.as_deref();
+            match commands::capacity(&args.file, seal_overhead, payload_file, format) {
+                Ok(()) => debug!("capacity printed"),
+                Err(e) => {
+                    error!("error {} when estimating the capacity of {}", e, &args.file.display());
+                    output::error(format, &e, false);
+                    return output::exit_code(&e);
+                }
+            }
+        }
+        PnnmeFunctions::Scan(args) => match commands::scan(&args.files, format) {
+            Ok(Verdict::Clean) => (),
+            Ok(_) => return ExitCode::from(output::EXIT_SUSPICIOUS),
+            // already reported with the file it comes from
+            Err(e) => return output::exit_code(&e),
         },
     }
 
-    Ok(())
+    ExitCode::SUCCESS
+}
+
+/// Method given by --method, or by --lsb. The options of the other methods
+/// are refused rather than ignored.
+fn method(
+    method: Option<Method>,
+    lsb: bool,
+    type_chunk: Option<&String>,
+    name: Option<&String>,
+    lsb_key: Option<&String>,
+    lsb_bits: Option<u8>,
+) -> anyhow::Result<Method> {
+    let method = match method {
+        _ if lsb => Method::Lsb,
+        Some(method) => method,
+        None => Method::Chunk,
+    };
+    match (method, type_chunk) {
+        (Method::Chunk, None) => bail!("--method chunk needs --type_chunk"),
+        (Method::Chunk, Some(_)) => (),
+        (_, Some(_)) => bail!("--type_chunk only applies to --method chunk"),
+        (_, None) if name.is_some() => bail!("--name only applies to --method chunk"),
+        (_, None) => (),
+    }
+    if name.is_some_and(|name| name.is_empty()) {
+        bail!("--name can't be empty");
+    }
+    if lsb_key.is_some() && method != Method::Lsb {
+        bail!("--lsb-key only applies to --method lsb");
+    }
+    if lsb_bits.is_some() && method != Method::Lsb {
+        bail!("--lsb-bits only applies to --method lsb");
+    }
+    Ok(method)
+}
+
+/// Where encode hides the payload : in chunks, in the pixels, after IEND or
+/// after the zlib stream
+fn placement(args: &EncodeArgs) -> anyhow::Result<Placement<'_>> {
+    let chunk_type = args.type_chunk.as_ref();
+    let name = args.name.as_ref();
+    let (key, bits) = (args.lsb_key.as_ref(), args.lsb_bits);
+    Ok(match method(args.method, args.lsb, chunk_type, name, key, bits)? {
+        Method::Chunk => Placement::Chunk {
+            chunk_type: chunk_type.cloned().unwrap_or_default(),
+            name: args.name.as_deref(),
+            max_chunk_size: args.max_chunk_size,
+        },
+        Method::Lsb => Placement::Lsb {
+            key: args.lsb_key.as_deref(),
+            bits: bits.unwrap_or(lsb::DEFAULT_BITS),
+        },
+        Method::Trailer => Placement::Trailer,
+        Method::IdatSlack => Placement::IdatSlack,
+    })
+}
+
+/// Where decode looks for the payload, like [`placement`]
+fn source(args: &DecodeArgs) -> anyhow::Result<Source<'_>> {
+    let chunk_type = args.type_chunk.as_ref();
+    let name = args.name.as_ref();
+    let (key, bits) = (args.lsb_key.as_ref(), args.lsb_bits);
+    Ok(match method(args.method, args.lsb, chunk_type, name, key, bits)? {
+        Method::Chunk => Source::Chunk {
+            chunk_type: chunk_type.cloned().unwrap_or_default(),
+            name: args.name.as_deref(),
+        },
+        Method::Lsb => Source::Lsb {
+            key: args.lsb_key.as_deref(),
+            bits: bits.unwrap_or(lsb::DEFAULT_BITS),
+        },
+        Method::Trailer => Source::Trailer,
+        Method::IdatSlack => Source::IdatSlack,
+    })
+}
+
+/// Encryption asked by --encrypt or --recipient
+fn seal(args: &EncodeArgs) -> anyhow::Result<Seal> {
+    if args.encrypt {
+        return Ok(Seal::Passphrase(commands::passphrase(true)?));
+    }
+    if args.recipients.is_empty() {
+        return Ok(Seal::Plain);
+    }
+    let recipients = args
+        .recipients
+        .iter()
+        .map(|r| Recipient::from_str(r))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Seal::Recipients(recipients))
+}
+
+/// Decryption asked by --decrypt or --identity
+fn unseal(args: &DecodeArgs) -> anyhow::Result<Unseal> {
+    if args.decrypt {
+        return Ok(Unseal::Passphrase(commands::passphrase(false)?));
+    }
+    match &args.identity {
+        Some(path) => Ok(Unseal::Identity(commands::read_identity(path)?)),
+        None => Ok(Unseal::Plain),
+    }
 }
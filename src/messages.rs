@@ -0,0 +1,249 @@
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::png::Png;
+use crate::reader::PngReader;
+use crate::signature;
+use crate::split::{self, Piece};
+use crate::{PngError, Result};
+use serde::Serialize;
+use std::io::Read;
+
+/// A message hidden in one chunk or split across several
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MessageInfo {
+    pub chunk_type: ChunkType,
+    /// Name given by encode --name, `None` for a message without a name
+    pub name: Option<String>,
+    /// Identifier shared by the pieces, in hex, `None` for a single raw chunk
+    pub id: Option<String>,
+    /// Indexes of the chunks holding the message, in the order of the file
+    pub chunks: Vec<usize>,
+    /// Bytes hidden, without the headers of the pieces
+    pub size: usize,
+    pub encrypted: bool,
+    /// False if pieces are missing
+    pub complete: bool,
+}
+
+/// The messages hidden in the chunks of `chunk_type`, or in every private
+/// ancillary chunk but the signature if `None`, in the order of their first
+/// chunk
+pub fn messages(png: &Png, chunk_type: Option<&ChunkType>) -> Vec<MessageInfo> {
+    let mut messages = Messages::default();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if is_candidate(chunk.chunk_type(), chunk_type) {
+            messages.add(index, chunk.chunk_type(), chunk.data());
+        }
+    }
+    messages.finish()
+}
+
+/// Like [`messages`], reading the PNG from `reader` : only the chunks which
+/// may hold a message are read, the others are skipped.
+pub fn messages_from<R: Read>(
+    reader: R,
+    chunk_type: Option<&ChunkType>,
+) -> Result<Vec<MessageInfo>> {
+    let mut messages = Messages::default();
+    let mut reader = PngReader::new(reader)?;
+    let mut index = 0;
+    while let Some(header) = reader.next_header()? {
+        if is_candidate(header.chunk_type(), chunk_type) {
+            let chunk = reader.read_body()?;
+            messages.add(index, chunk.chunk_type(), chunk.data());
+        }
+        index += 1;
+    }
+    Ok(messages.finish())
+}
+
+fn is_candidate(t: &ChunkType, wanted: Option<&ChunkType>) -> bool {
+    match wanted {
+        Some(wanted) => t == wanted,
+        None => !t.is_critical() && !t.is_public() && t.bytes() != signature::CHUNK_TYPE,
+    }
+}
+
+/// The messages found so far, with the number of pieces each one expects
+#[derive(Default)]
+struct Messages {
+    messages: Vec<MessageInfo>,
+    totals: Vec<u32>,
+}
+
+impl Messages {
+    /// Add the chunk at `index`, a raw message or a piece of one
+    fn add(&mut self, index: usize, t: &ChunkType, data: &[u8]) {
+        let piece = match Piece::parse(data) {
+            Ok(Some(piece)) => piece,
+            _ => {
+                self.messages.push(MessageInfo {
+                    chunk_type: t.clone(),
+                    name: None,
+                    id: None,
+                    chunks: vec![index],
+                    size: data.len(),
+                    encrypted: crypto::is_sealed(data),
+                    complete: true,
+                });
+                self.totals.push(1);
+                return;
+            }
+        };
+        let id = piece.id_hex();
+        let known = self
+            .messages
+            .iter()
+            .position(|m| m.chunk_type == *t && m.id.as_deref() == Some(id.as_str()));
+        match known {
+            Some(i) => {
+                self.messages[i].chunks.push(index);
+                self.messages[i].size += piece.data.len();
+                if piece.seq == 0 {
+                    self.messages[i].encrypted = crypto::is_sealed(piece.data);
+                }
+            }
+            None => {
+                self.messages.push(MessageInfo {
+                    chunk_type: t.clone(),
+                    name: piece.name.map(str::to_string),
+                    id: Some(id),
+                    chunks: vec![index],
+                    size: piece.data.len(),
+                    encrypted: piece.seq == 0 && crypto::is_sealed(piece.data),
+                    complete: false,
+                });
+                self.totals.push(piece.total);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<MessageInfo> {
+        for (message, total) in self.messages.iter_mut().zip(self.totals) {
+            message.complete = message.chunks.len() == total as usize;
+        }
+        self.messages
+    }
+}
+
+/// The data of the message hidden in the chunks of `chunk_type`, the first one
+/// if `name` is `None`, reassembled from its pieces.
+///
+/// Returns [`PngError::ChunkNotFound`] if there is no such chunk,
+/// [`PngError::MessageNotFound`] if no message has this name and
+/// [`PngError::InvalidPayload`] if several have it.
+pub fn read_message(png: &Png, chunk_type: &ChunkType, name: Option<&str>) -> Result<Vec<u8>> {
+    let chunks = png.chunks().iter().filter(|c| c.chunk_type() == chunk_type);
+    let name = match name {
+        None if chunks.clone().next().is_none() => {
+            return Err(PngError::ChunkNotFound(chunk_type.to_string()))
+        }
+        None => return split::reassemble(chunks.map(|c| c.data())),
+        Some(name) => name,
+    };
+    let named: Vec<MessageInfo> = messages(png, Some(chunk_type))
+        .into_iter()
+        .filter(|m| m.name.as_deref() == Some(name))
+        .collect();
+    match named.as_slice() {
+        [] => Err(PngError::MessageNotFound(name.to_string())),
+        [message] => split::reassemble(message.chunks.iter().map(|&i| png.chunks()[i].data())),
+        _ => Err(PngError::InvalidPayload(format!(
+            "{} messages are named {} in the {} chunks",
+            named.len(),
+            name,
+            chunk_type
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    /// A PNG with a raw message, two named messages of 3 and 1 pieces, and a
+    /// message in another chunk type
+    fn testing_png() -> Png {
+        let mut chunks = vec![chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0])];
+        chunks.push(chunk("ruSt", b"plain message"));
+        let long = split::split(&[1u8; 90], 60, Some("long")).unwrap();
+        let short = split::split(b"short message", 60, Some("short")).unwrap();
+        chunks.push(chunk("ruSt", &long[0]));
+        chunks.push(chunk("ruSt", &short[0]));
+        chunks.extend(long[1..].iter().map(|p| chunk("ruSt", p)));
+        chunks.push(chunk("otHr", b"other"));
+        chunks.push(chunk("tEXt", b"Author\0me"));
+        chunks.push(chunk("IEND", b""));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_list_messages() {
+        let png = testing_png();
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let messages = messages(&png, Some(&rust));
+        let names: Vec<Option<&str>> = messages.iter().map(|m| m.name.as_deref()).collect();
+        assert_eq!(names, [None, Some("long"), Some("short")]);
+        assert_eq!(messages[0].chunks, [1]);
+        assert_eq!(messages[1].chunks, [2, 4, 5]);
+        assert_eq!((messages[1].size, messages[1].complete), (90, true));
+        assert!(messages.iter().all(|m| !m.encrypted));
+
+        let all = super::messages(&png, None);
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[3].chunk_type.to_string(), "otHr");
+    }
+
+    #[test]
+    fn test_list_messages_from_reader() {
+        let png = testing_png();
+        let bytes = png.as_bytes();
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let read = messages_from(bytes.as_slice(), Some(&rust)).unwrap();
+        assert_eq!(read, messages(&png, Some(&rust)));
+        assert_eq!(messages_from(bytes.as_slice(), None).unwrap(), messages(&png, None));
+        assert!(messages_from(&b"not a png"[..], None).is_err());
+    }
+
+    #[test]
+    fn test_incomplete_message() {
+        let mut chunks = testing_png().chunks().to_vec();
+        chunks.remove(4);
+        let png = Png::from_chunks(chunks);
+        let messages = messages(&png, Some(&ChunkType::from_str("ruSt").unwrap()));
+        assert!(!messages[1].complete);
+        assert!(messages[2].complete);
+    }
+
+    #[test]
+    fn test_read_message() {
+        let png = testing_png();
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(read_message(&png, &rust, None).unwrap(), b"plain message");
+        assert_eq!(read_message(&png, &rust, Some("short")).unwrap(), b"short message");
+        assert_eq!(read_message(&png, &rust, Some("long")).unwrap(), [1u8; 90]);
+
+        let result = read_message(&png, &rust, Some("other"));
+        assert!(matches!(result, Err(PngError::MessageNotFound(n)) if n == "other"));
+        let missing = ChunkType::from_str("noNe").unwrap();
+        let result = read_message(&png, &missing, None);
+        assert!(matches!(result, Err(PngError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_ambiguous_name() {
+        let mut chunks = testing_png().chunks().to_vec();
+        let again = split::split(b"again", 60, Some("short")).unwrap();
+        chunks.insert(1, chunk("ruSt", &again[0]));
+        let png = Png::from_chunks(chunks);
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let result = read_message(&png, &rust, Some("short"));
+        assert!(matches!(result, Err(PngError::InvalidPayload(_))));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::args::Format;
+use pngme::PngError;
+use serde::Serialize;
+use serde_json::json;
+use std::process::ExitCode;
+
+/// Exit codes of the process, 2 is used by clap for usage errors
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_IO: u8 = 3;
+pub const EXIT_INVALID_PNG: u8 = 4;
+pub const EXIT_CRC: u8 = 5;
+pub const EXIT_CHUNK_NOT_FOUND: u8 = 6;
+pub const EXIT_AUTHENTICATION: u8 = 7;
+pub const EXIT_CHECK_WARNINGS: u8 = 8;
+pub const EXIT_SUSPICIOUS: u8 = 9;
+
+/// Write the result of a command as JSON. It goes to stdout, unless stdout
+/// already carries the PNG written by the command : then it goes to stderr.
+pub fn json<T: Serialize>(value: &T, stdout_taken: bool) {
+    let s = serde_json::to_string_pretty(value).expect("results are always serializable");
+    if stdout_taken {
+        eprintln!("{}", s);
+    } else {
+        println!("{}", s);
+    }
+}
+
+/// Write the error as JSON when this format is asked, it is always logged anyway
+pub fn error(format: Format, e: &anyhow::Error, stdout_taken: bool) {
+    if format == Format::Json {
+        let value = json!({
+            "error": {
+                "code": error_code(e),
+                "message": e.to_string(),
+            }
+        });
+        json(&value, stdout_taken);
+    }
+}
+
+/// Stable identifier of the error for scripts : the code of the `PngError`
+/// if there is one, `io` for the other I/O errors
+pub fn error_code(e: &anyhow::Error) -> &'static str {
+    if let Some(png_error) = e.downcast_ref::<PngError>() {
+        return png_error.code();
+    }
+    if e.downcast_ref::<std::io::Error>().is_some() {
+        return "io";
+    }
+    "error"
+}
+
+/// Exit code of the process for this error
+pub fn exit_code(e: &anyhow::Error) -> ExitCode {
+    let code = match e.downcast_ref::<PngError>() {
+        Some(PngError::Io(_)) => EXIT_IO,
+        Some(PngError::CrcMismatch { .. }) => EXIT_CRC,
+        Some(PngError::ChunkNotFound(_)) => EXIT_CHUNK_NOT_FOUND,
+        Some(PngError::MessageNotFound(_)) => EXIT_CHUNK_NOT_FOUND,
+        Some(PngError::NoTrailer) => EXIT_CHUNK_NOT_FOUND,
+        Some(PngError::AuthenticationFailed) => EXIT_AUTHENTICATION,
+        Some(PngError::SignatureMismatch) => EXIT_AUTHENTICATION,
+        Some(PngError::PayloadTooLarge { .. }) => EXIT_FAILURE,
+        Some(PngError::InvalidPayload(_)) => EXIT_FAILURE,
+        Some(PngError::InvalidUtf8(_)) => EXIT_FAILURE,
+        Some(PngError::UnsupportedImage(_)) => EXIT_FAILURE,
+        Some(_) => EXIT_INVALID_PNG,
+        None if e.downcast_ref::<std::io::Error>().is_some() => EXIT_IO,
+        None => EXIT_FAILURE,
+    };
+    ExitCode::from(code)
+}
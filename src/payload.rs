@@ -0,0 +1,287 @@
+use crate::{PngError, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+/// First bytes of a payload with a header
+pub const MAGIC: [u8; 5] = *b"PNGMP";
+/// Version of the layout of the payload header. Version 1 had no compression.
+pub const VERSION: u8 = 2;
+/// Largest content decompressed by default, against zip bombs
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+/// Bytes of the payload header, without the file name
+pub const HEADER_LEN: usize = MAGIC.len() + 3 + 2 + 8 + HASH_LEN;
+/// The payload is a file, its name is in the header
+const FLAG_FILE: u8 = 1;
+const HASH_LEN: usize = 32;
+
+/// How the content of a payload is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    /// Raw deflate stream, RFC 1951
+    Deflate = 1,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(invalid(&format!("unknown compression algorithm {}", value))),
+        }
+    }
+}
+
+/// What is hidden in a PNG : a text message or the content of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    /// Name of the file carried, `None` for a message
+    pub file_name: Option<String>,
+    pub content: Vec<u8>,
+}
+
+impl Payload {
+    /// A text message
+    pub fn message(text: &str) -> Payload {
+        Payload {
+            file_name: None,
+            content: text.as_bytes().to_vec(),
+        }
+    }
+
+    /// The content of a file named `file_name`
+    pub fn file(file_name: &str, content: Vec<u8>) -> Payload {
+        Payload {
+            file_name: Some(file_name.to_string()),
+            content,
+        }
+    }
+
+    /// True if `data` starts like a payload with a header
+    pub fn has_header(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+
+    /// The bytes to store in a chunk. A message which is not compressed is
+    /// stored as is, otherwise a header holds the compression, the name of the
+    /// file if any, and the size and SHA-256 of the content before compression :
+    ///
+    /// ```text
+    /// "PNGMP" | version | flags | compression | name length (u16) | name | size (u64) | SHA-256 (32) | data
+    /// ```
+    pub fn to_bytes(&self, compression: Compression) -> Result<Vec<u8>> {
+        if self.file_name.is_none() && compression == Compression::None {
+            return Ok(self.content.clone());
+        }
+        let name = self.file_name.as_deref().unwrap_or("").as_bytes();
+        if name.len() > u16::MAX as usize {
+            return Err(invalid("file name too long"));
+        }
+        let flags = if self.file_name.is_some() { FLAG_FILE } else { 0 };
+        let mut bytes = Vec::with_capacity(HEADER_LEN + name.len() + self.content.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&[VERSION, flags, compression as u8]);
+        bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(name);
+        bytes.extend_from_slice(&(self.content.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&Sha256::digest(&self.content));
+        match compression {
+            Compression::None => bytes.extend_from_slice(&self.content),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(bytes, flate2::Compression::best());
+                encoder.write_all(&self.content)?;
+                bytes = encoder.finish()?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Parse the bytes written by [`Payload::to_bytes`] with the default limit
+    /// on the size of the content
+    pub fn from_bytes(data: &[u8]) -> Result<Payload> {
+        Payload::from_bytes_limited(data, DEFAULT_MAX_SIZE)
+    }
+
+    /// Parse the bytes written by [`Payload::to_bytes`], decompressing the
+    /// content and checking its size and hash. Bytes without a header are a
+    /// message.
+    ///
+    /// Returns [`PngError::PayloadTooLarge`] if the content is above
+    /// `max_size` bytes, without decompressing more than that.
+    pub fn from_bytes_limited(data: &[u8], max_size: u64) -> Result<Payload> {
+        if !Payload::has_header(data) {
+            return Ok(Payload {
+                file_name: None,
+                content: data.to_vec(),
+            });
+        }
+        let mut rest = &data[MAGIC.len()..];
+        let [version, flags] = take::<2>(&mut rest)?;
+        let compression = match version {
+            1 => Compression::None,
+            VERSION => Compression::try_from(take::<1>(&mut rest)?[0])?,
+            _ => return Err(invalid(&format!("unsupported payload version {}", version))),
+        };
+        if flags & !FLAG_FILE != 0 {
+            return Err(invalid(&format!("unknown payload flags {:#04x}", flags)));
+        }
+        let name_len = u16::from_be_bytes(take::<2>(&mut rest)?) as usize;
+        if rest.len() < name_len {
+            return Err(invalid("truncated header"));
+        }
+        let (name, after) = rest.split_at(name_len);
+        rest = after;
+        let name = String::from_utf8(name.to_vec())?;
+        let size = u64::from_be_bytes(take::<8>(&mut rest)?);
+        let hash = take::<HASH_LEN>(&mut rest)?;
+        if size > max_size {
+            return Err(PngError::PayloadTooLarge { limit: max_size });
+        }
+
+        let content = match compression {
+            Compression::None => rest.to_vec(),
+            Compression::Deflate => {
+                let mut content = Vec::new();
+                DeflateDecoder::new(rest)
+                    .take(max_size.saturating_add(1))
+                    .read_to_end(&mut content)
+                    .map_err(|e| invalid(&format!("corrupted compressed data : {}", e)))?;
+                if content.len() as u64 > max_size {
+                    return Err(PngError::PayloadTooLarge { limit: max_size });
+                }
+                content
+            }
+        };
+        if content.len() as u64 != size {
+            return Err(invalid(&format!(
+                "content is {} bytes instead of {}",
+                content.len(),
+                size
+            )));
+        }
+        if Sha256::digest(&content).as_slice() != hash {
+            return Err(invalid("SHA-256 of the content doesn't match"));
+        }
+        Ok(Payload {
+            file_name: (flags & FLAG_FILE != 0).then_some(name),
+            content,
+        })
+    }
+
+    /// The content as text, for a message
+    pub fn text(&self) -> Result<String> {
+        Ok(String::from_utf8(self.content.clone())?)
+    }
+}
+
+/// Remove the first `N` bytes of `rest` and return them
+fn take<const N: usize>(rest: &mut &[u8]) -> Result<[u8; N]> {
+    if rest.len() < N {
+        return Err(invalid("truncated header"));
+    }
+    let (head, tail) = rest.split_at(N);
+    *rest = tail;
+    Ok(head.try_into().unwrap())
+}
+
+fn invalid(reason: &str) -> PngError {
+    PngError::InvalidPayload(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_is_stored_as_is() {
+        let payload = Payload::message("secret message");
+        assert_eq!(payload.to_bytes(Compression::None).unwrap(), b"secret message");
+        let parsed = Payload::from_bytes(b"secret message").unwrap();
+        assert_eq!(parsed, payload);
+        assert_eq!(parsed.text().unwrap(), "secret message");
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let content = vec![0, 159, 146, 150, 255];
+        let payload = Payload::file("key.bin", content.clone());
+        let bytes = payload.to_bytes(Compression::None).unwrap();
+        assert!(Payload::has_header(&bytes));
+        assert_eq!(bytes.len(), 5 + 5 + 7 + 8 + 32 + 5);
+        assert_eq!(bytes.len(), HEADER_LEN + 7 + 5);
+
+        let parsed = Payload::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.file_name.as_deref(), Some("key.bin"));
+        assert_eq!(parsed.content, content);
+        assert!(parsed.text().is_err());
+    }
+
+    #[test]
+    fn test_version_1() {
+        // no compression byte
+        let mut bytes = b"PNGMP\x01\x01\x00\x01a".to_vec();
+        bytes.extend_from_slice(&2u64.to_be_bytes());
+        bytes.extend_from_slice(&Sha256::digest(b"hi"));
+        bytes.extend_from_slice(b"hi");
+        let parsed = Payload::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, Payload::file("a", b"hi".to_vec()));
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let text = "a long log line, repeated\n".repeat(100);
+        let payload = Payload::message(&text);
+        let bytes = payload.to_bytes(Compression::Deflate).unwrap();
+        assert!(bytes.len() < 200);
+        assert_eq!(Payload::from_bytes(&bytes).unwrap(), payload);
+
+        let file = Payload::file("log.txt", text.into_bytes());
+        let bytes = file.to_bytes(Compression::Deflate).unwrap();
+        assert_eq!(Payload::from_bytes(&bytes).unwrap(), file);
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let bomb = Payload::message(&"0".repeat(1 << 20));
+        let bytes = bomb.to_bytes(Compression::Deflate).unwrap();
+        let result = Payload::from_bytes_limited(&bytes, 1000);
+        assert!(matches!(result, Err(PngError::PayloadTooLarge { limit: 1000 })));
+
+        // a header lying about the size doesn't get past the limit either
+        let mut lying = bytes.clone();
+        lying[10..18].copy_from_slice(&10u64.to_be_bytes());
+        let result = Payload::from_bytes_limited(&lying, 1000);
+        assert!(matches!(result, Err(PngError::PayloadTooLarge { limit: 1000 })));
+
+        // no limit at all doesn't overflow
+        let payload = Payload::from_bytes_limited(&bytes, u64::MAX).unwrap();
+        assert_eq!(payload, bomb);
+    }
+
+    #[test]
+    fn test_corrupted_file() {
+        let payload = Payload::file("notes.txt", b"content".to_vec());
+        let bytes = payload.to_bytes(Compression::None).unwrap();
+
+        let mut altered = bytes.clone();
+        *altered.last_mut().unwrap() ^= 1;
+        assert!(matches!(Payload::from_bytes(&altered), Err(PngError::InvalidPayload(_))));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(Payload::from_bytes(truncated), Err(PngError::InvalidPayload(_))));
+        assert!(matches!(Payload::from_bytes(&bytes[..12]), Err(PngError::InvalidPayload(_))));
+
+        let mut other_version = bytes.clone();
+        other_version[5] = 9;
+        assert!(matches!(Payload::from_bytes(&other_version), Err(PngError::InvalidPayload(_))));
+
+        let mut other_compression = bytes.clone();
+        other_compression[7] = 9;
+        let result = Payload::from_bytes(&other_compression);
+        assert!(matches!(result, Err(PngError::InvalidPayload(_))));
+    }
+}
@@ -0,0 +1,389 @@
+use crate::chunk::Chunk;
+use crate::idat;
+use crate::ihdr::Ihdr;
+use crate::png::Png;
+use crate::{PngError, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::{Read, Write};
+
+/// Starting column, starting row, column step and row step of the 7 passes of
+/// Adam7 interlacing
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Largest image decoded, in bytes of filtered scanlines : larger ones are
+/// refused rather than inflated in memory
+pub const MAX_DECODED_LEN: usize = 512 * 1024 * 1024;
+
+/// Deflate can't compress more than this, a zlib stream larger than its input
+/// times this ratio is corrupted
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// One reduced image : the whole image, or a pass of an interlaced one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub width: u32,
+    pub height: u32,
+    /// Offset of the first row in [`Pixels::data`]
+    pub offset: usize,
+    /// Bytes of one row, without the filter type byte
+    pub row_len: usize,
+}
+
+/// The decoded image data : the inflated and unfiltered scanlines, without
+/// their filter type byte, pass after pass if the image is interlaced
+#[derive(Debug, Clone)]
+pub struct Pixels {
+    ihdr: Ihdr,
+    passes: Vec<Pass>,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    /// Inflate and unfilter the IDAT chunks of `png`.
+    ///
+    /// Returns [`PngError::InvalidImageData`] if the compressed stream is
+    /// corrupted, holds fewer bytes than the IHDR asks for, or uses an unknown
+    /// filter type, and [`PngError::UnsupportedImage`] if the image is larger
+    /// than [`MAX_DECODED_LEN`].
+    pub fn from_png(png: &Png) -> Result<Pixels> {
+        let ihdr = png.header_info()?;
+        let passes = passes(&ihdr)?;
+        let filtered_len = filtered_len(&passes)?;
+        if filtered_len > MAX_DECODED_LEN {
+            return Err(PngError::UnsupportedImage(format!(
+                "{} bytes of scanlines, more than the {} decoded at most",
+                filtered_len, MAX_DECODED_LEN
+            )));
+        }
+        let compressed = idat::concat(png)?;
+        if filtered_len / MAX_DEFLATE_RATIO > compressed.len() {
+            return Err(invalid(format!(
+                "{} bytes of zlib stream can't hold {} bytes of scanlines",
+                compressed.len(),
+                filtered_len
+            )));
+        }
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(filtered_len as u64)
+            .read_to_end(&mut filtered)
+            .map_err(|e| invalid(format!("corrupted zlib stream : {}", e)))?;
+        if filtered.len() < filtered_len {
+            return Err(invalid(format!(
+                "{} bytes of scanlines instead of {}",
+                filtered.len(),
+                filtered_len
+            )));
+        }
+
+        let pixel_len = pixel_len(&ihdr);
+        let mut data = Vec::with_capacity(filtered_len);
+        let mut rows = filtered.as_slice();
+        for pass in &passes {
+            let mut previous = vec![0u8; pass.row_len];
+            for _ in 0..pass.height {
+                let (row, rest) = rows.split_at(pass.row_len + 1);
+                rows = rest;
+                let mut current = row[1..].to_vec();
+                unfilter(row[0], &mut current, &previous, pixel_len)?;
+                data.extend_from_slice(&current);
+                previous = current;
+            }
+        }
+        Ok(Pixels { ihdr, passes, data })
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    /// The passes of the image, one if it is not interlaced
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// The unfiltered scanlines, back to back
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Filter and deflate the scanlines into IDAT chunks of at most
+    /// `max_chunk_len` bytes. Each row gets the filter type giving the
+    /// smallest sum of absolute differences, as libpng does.
+    pub fn to_idat(&self, max_chunk_len: usize) -> Result<Vec<Chunk>> {
+        idat::rechunk(&self.deflate()?, max_chunk_len)
+    }
+
+    /// A copy of `png` holding these pixels : its IDAT chunks are replaced by
+    /// new ones, see [`idat::replace`]
+    pub fn replace_in(&self, png: &Png) -> Result<Png> {
+        idat::replace(png, &self.deflate()?)
+    }
+
+    fn deflate(&self) -> Result<Vec<u8>> {
+        let pixel_len = pixel_len(&self.ihdr);
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        for pass in &self.passes {
+            let mut previous = vec![0u8; pass.row_len];
+            for row in 0..pass.height as usize {
+                let start = pass.offset + row * pass.row_len;
+                let current = &self.data[start..start + pass.row_len];
+                encoder.write_all(&best_filter(current, &previous, pixel_len))?;
+                previous = current.to_vec();
+            }
+        }
+        Ok(encoder.finish()?)
+    }
+}
+
+/// The reduced images stored in the IDAT data, the 7 passes of Adam7 without
+/// the empty ones if the image is interlaced. Returns
+/// [`PngError::InvalidImageData`] if their size overflows.
+pub fn passes(ihdr: &Ihdr) -> Result<Vec<Pass>> {
+    let sizes: Vec<(u32, u32)> = if ihdr.interlaced() {
+        ADAM7
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let count = |size: u32, start: u32, step: u32| (size + step - 1 - start) / step;
+                (count(ihdr.width(), x0, dx), count(ihdr.height(), y0, dy))
+            })
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .collect()
+    } else {
+        vec![(ihdr.width(), ihdr.height())]
+    };
+    let overflow = || invalid(format!("image of {}x{} too large", ihdr.width(), ihdr.height()));
+    let mut offset: usize = 0;
+    sizes
+        .into_iter()
+        .map(|(width, height)| {
+            let row_len = (width as usize)
+                .checked_mul(ihdr.bits_per_pixel() as usize)
+                .ok_or_else(overflow)?
+                .div_ceil(8);
+            let pass = Pass {
+                width,
+                height,
+                offset,
+                row_len,
+            };
+            offset = row_len
+                .checked_mul(height as usize)
+                .and_then(|len| offset.checked_add(len))
+                .ok_or_else(overflow)?;
+            Ok(pass)
+        })
+        .collect()
+}
+
+/// Bytes of the inflated image data of `ihdr`, the filter type bytes included.
+/// Returns [`PngError::InvalidImageData`] if it overflows.
+pub fn decoded_len(ihdr: &Ihdr) -> Result<usize> {
+    filtered_len(&passes(ihdr)?)
+}
+
+fn filtered_len(passes: &[Pass]) -> Result<usize> {
+    passes
+        .iter()
+        .try_fold(0usize, |len, p| {
+            (p.row_len + 1)
+                .checked_mul(p.height as usize)
+                .and_then(|pass_len| len.checked_add(pass_len))
+        })
+        .ok_or_else(|| invalid("image too large".to_string()))
+}
+
+/// Bytes of one pixel, 1 for bit depths below 8, the distance used by filters
+fn pixel_len(ihdr: &Ihdr) -> usize {
+    (ihdr.bits_per_pixel() as usize / 8).max(1)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Predictor of the byte at `i` for filter type `filter`
+fn predict(filter: u8, row: &[u8], previous: &[u8], i: usize, pixel_len: usize) -> u8 {
+    let a = if i >= pixel_len { row[i - pixel_len] } else { 0 };
+    let b = previous[i];
+    let c = if i >= pixel_len { previous[i - pixel_len] } else { 0 };
+    match filter {
+        1 => a,
+        2 => b,
+        3 => ((a as u16 + b as u16) / 2) as u8,
+        4 => paeth(a, b, c),
+        _ => 0,
+    }
+}
+
+/// Undo the filter of `row` in place, `previous` being the row above unfiltered
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], pixel_len: usize) -> Result<()> {
+    if filter > 4 {
+        return Err(invalid(format!("unknown filter type {}", filter)));
+    }
+    for i in 0..row.len() {
+        row[i] = row[i].wrapping_add(predict(filter, row, previous, i, pixel_len));
+    }
+    Ok(())
+}
+
+/// `row` filtered with the type minimizing the sum of absolute differences,
+/// preceded by this type
+fn best_filter(row: &[u8], previous: &[u8], pixel_len: usize) -> Vec<u8> {
+    (0..=4u8)
+        .map(|filter| {
+            let mut filtered = Vec::with_capacity(row.len() + 1);
+            filtered.push(filter);
+            for i in 0..row.len() {
+                filtered.push(row[i].wrapping_sub(predict(filter, row, previous, i, pixel_len)));
+            }
+            filtered
+        })
+        .min_by_key(|f| f[1..].iter().map(|&b| (b as i8).unsigned_abs() as u64).sum::<u64>())
+        .expect("there are 5 filter types")
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidImageData(reason)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    /// A PNG of `width` x `height` pixels of `color_type` with varied samples,
+    /// each row filtered with a different type
+    pub(crate) fn testing_png(width: u32, height: u32, color_type: u8, interlaced: bool) -> Png {
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, interlaced as u8]);
+        let header = Ihdr::try_from(ihdr.as_slice()).unwrap();
+        let pixel_len = pixel_len(&header);
+        let mut filtered = Vec::new();
+        let mut value = 0u32;
+        for pass in passes(&header).unwrap() {
+            let mut previous = vec![0u8; pass.row_len];
+            for row in 0..pass.height {
+                let current: Vec<u8> = (0..pass.row_len)
+                    .map(|_| {
+                        value = value.wrapping_mul(1103515245).wrapping_add(12345);
+                        (value >> 16) as u8
+                    })
+                    .collect();
+                let filter = (row % 5) as u8;
+                filtered.push(filter);
+                for i in 0..current.len() {
+                    let predicted = predict(filter, &current, &previous, i, pixel_len);
+                    filtered.push(current[i].wrapping_sub(predicted));
+                }
+                previous = current;
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&filtered).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let chunk =
+            |t: &str, data: &[u8]| Chunk::new(ChunkType::from_str(t).unwrap(), data.to_vec());
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        Png::from_chunks(vec![
+            chunk("IHDR", &ihdr),
+            chunk("tEXt", b"Comment\0kept"),
+            chunk("IDAT", first),
+            chunk("IDAT", second),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_passes() {
+        let png = testing_png(10, 5, 2, false);
+        let ihdr = png.header_info().unwrap();
+        let passes = passes(&ihdr).unwrap();
+        assert_eq!(passes.len(), 1);
+        assert_eq!((passes[0].width, passes[0].row_len), (10, 30));
+
+        let png = testing_png(10, 5, 2, true);
+        let sizes: Vec<(u32, u32)> = super::passes(&png.header_info().unwrap())
+            .unwrap()
+            .iter()
+            .map(|p| (p.width, p.height))
+            .collect();
+        assert_eq!(sizes, [(2, 1), (1, 1), (3, 1), (2, 2), (5, 1), (5, 3), (10, 2)]);
+        let pixels: u32 = sizes.iter().map(|(w, h)| w * h).sum();
+        assert_eq!(pixels, 50);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (color_type, interlaced) in [(2, false), (6, false), (0, true), (4, true)] {
+            let png = testing_png(13, 7, color_type, interlaced);
+            let pixels = Pixels::from_png(&png).unwrap();
+            let rewritten = pixels.replace_in(&png).unwrap();
+            let types: Vec<String> =
+                rewritten.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+            assert_eq!(types[..2], ["IHDR", "tEXt"]);
+            assert_eq!(types.last().unwrap(), "IEND");
+            assert_eq!(Pixels::from_png(&rewritten).unwrap().data(), pixels.data());
+        }
+    }
+
+    #[test]
+    fn test_corrupted_image_data() {
+        let png = testing_png(4, 4, 2, false);
+        let mut chunks = png.chunks().to_vec();
+        chunks.remove(3);
+        let truncated = Png::from_chunks(chunks);
+        let result = Pixels::from_png(&truncated);
+        assert!(matches!(result, Err(PngError::InvalidImageData(_))));
+
+        let mut chunks = png.chunks().to_vec();
+        chunks.retain(|c| c.chunk_type().to_string() != "IDAT");
+        let result = Pixels::from_png(&Png::from_chunks(chunks));
+        assert!(matches!(result, Err(PngError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_huge_header() {
+        let png = testing_png(4, 4, 2, false);
+        let with_header = |size: u32, bit_depth: u8, color_type: u8| {
+            let mut ihdr = size.to_be_bytes().to_vec();
+            ihdr.extend_from_slice(&size.to_be_bytes());
+            ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+            let mut chunks = png.chunks().to_vec();
+            chunks[0] = Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr);
+            Pixels::from_png(&Png::from_chunks(chunks))
+        };
+        // larger than the limit, not allocated
+        let result = with_header(100_000, 16, 6);
+        assert!(matches!(result, Err(PngError::UnsupportedImage(_))));
+        // the size overflows
+        let result = with_header(0x7fff_ffff, 16, 6);
+        assert!(matches!(result, Err(PngError::InvalidImageData(_))));
+        // below the limit, but a few bytes of IDAT can't inflate to it
+        let result = with_header(60_000, 1, 0);
+        assert!(matches!(result, Err(PngError::InvalidImageData(m)) if m.contains("can't hold")));
+    }
+}
@@ -1,58 +1,74 @@
 use crate::chunk::Chunk;
-use crate::Result;
-use anyhow::bail;
+use crate::ihdr::Ihdr;
+use crate::reader::PngReader;
+use crate::{PngError, Result};
 use std::fmt::Display;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
+/// A PNG file : the 8 bytes signature followed by a list of chunks, and the
+/// bytes found after `IEND` if any
+#[derive(Debug)]
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
+    trailer: Vec<u8>,
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+    /// Creates a `Png` with the standard header from a list of chunks, without
+    /// trailer
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         let header = Self::STANDARD_HEADER;
         //let chunk_ihdr = Chunk::new(ChunkType::TryFrom([0x49, 0x48, 0x44, 0x52]), Vec::new());
         // vérifier que le premier chunk est de type IHDR
 
         // vérifier que le dernier chunk est de type IEND
-        if chunks[0].chunk_type().to_string() != "IHDR" {
-            println!("First chunk of the PNG is not IHDR");
+        if chunks.first().map(|c| c.chunk_type().to_string()) != Some("IHDR".to_string()) {
+            log::warn!("First chunk of the PNG is not IHDR");
         }
-        if chunks[chunks.len() - 1].chunk_type().to_string() != "IEND" {
-            println!("Last chunk of the PNG is not IEND");
+        if chunks.last().map(|c| c.chunk_type().to_string()) != Some("IEND".to_string()) {
+            log::warn!("Last chunk of the PNG is not IEND");
         }
 
         let png: Png = Png {
             header,
             chunks,
+            trailer: Vec::new(),
         };
         png
     }
 
     /// Creates a `Png` from a file path
     pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
-        let filepath = path.as_ref();
-        let filename = filepath.file_name();
-        if let Some(name) = filename {
-            let file = File::open(name)?;
-            let mut reader = io::BufReader::new(file);
-            let mut buffer: Vec<u8> = Vec::new();
-            reader.read_to_end(&mut buffer)?;
-            let png: Png = Png::try_from(buffer.as_slice())?;
-            return Ok(png);
-        }
-        bail!("wrong filename")
+        let file = File::open(path.as_ref())?;
+        Png::from_reader(io::BufReader::new(file))
     }
 
+    /// Creates a `Png` from any reader, chunks are validated while read and
+    /// what follows `IEND` is kept as the trailer
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = PngReader::new(reader)?;
+        let chunks = (&mut reader).collect::<Result<Vec<Chunk>>>()?;
+        let mut png = Png::from_chunks(chunks);
+        png.trailer = reader.read_trailer()?;
+        Ok(png)
+    }
+
+    /// Add a chunk just before the `IEND` chunk, or at the end if there is none
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.insert(self.chunks.len() - 2, chunk);
+        let end = self.chunks.len();
+        let index = match self.chunks.last() {
+            Some(c) if c.chunk_type().bytes() == *b"IEND" => end - 1,
+            _ => end,
+        };
+        self.chunks.insert(index, chunk);
     }
 
+    /// Remove the first chunk of the given type and return it
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         for (index,c) in self.chunks.iter().enumerate() {
             if c.chunk_type().to_string() == chunk_type {
@@ -60,17 +76,41 @@ impl Png {
                 return Ok(removed_chunk);
             }
         }
-        bail!("chunk type not found in this png");
+        Err(PngError::ChunkNotFound(chunk_type.to_string()))
     }
 
-    pub fn _header(&self) -> &[u8; 8] {
+    pub fn header(&self) -> &[u8; 8] {
         &self.header
     }
 
-    pub fn _chunks(&self) -> &[Chunk] {
+    pub fn chunks(&self) -> &[Chunk] {
         self.chunks.as_slice()
     }
 
+    /// Bytes after the `IEND` chunk, ignored by decoders
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Replace the bytes written after the `IEND` chunk
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    /// Remove the bytes after the `IEND` chunk and return them
+    pub fn remove_trailer(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailer)
+    }
+
+    /// Decoded header of the image, from the IHDR chunk which must come first
+    pub fn header_info(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(c) if c.chunk_type().bytes() == *b"IHDR" => Ihdr::try_from(c),
+            _ => Err(PngError::ChunkNotFound("IHDR".to_string())),
+        }
+    }
+
+    /// First chunk of the given type, if any
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         // for c in &self.chunks {
         //     if c.chunk_type().to_string() == chunk_type {
@@ -81,13 +121,27 @@ impl Png {
         self.chunks.iter().find(|&c| c.chunk_type().to_string() == chunk_type)
     }
 
+    /// Every chunk of the given type, in the order of the file
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |c| c.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Serialize the PNG : header followed by every chunk and the trailer
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut v = Vec::new();
-        v.append(&mut self.header.to_vec());
+        self.write_to(&mut v).expect("writing to a Vec can't fail");
+        v
+    }
+
+    /// Write the PNG to `writer` chunk by chunk, without building it in memory
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.header)?;
         for c in &self.chunks {
-            v.append(&mut c.as_bytes().clone());
+            c.write_to(writer)?;
         }
-        v
+        writer.write_all(&self.trailer)
     }
 }
 
@@ -102,31 +156,49 @@ impl Display for Png {
 }
 
 impl TryFrom<&[u8]> for Png {
-    type Error = crate::Error;
+    type Error = PngError;
 
     fn try_from(array: &[u8]) -> std::result::Result<Self, Self::Error> {
         // check presence of header
         let mut index;
-        if array[0..Png::STANDARD_HEADER.len()] == Png::STANDARD_HEADER {
+        if array.get(0..Png::STANDARD_HEADER.len()) == Some(&Png::STANDARD_HEADER[..]) {
             index = Png::STANDARD_HEADER.len();
         } else {
-            bail!("No valid header in input bytes");
+            return Err(PngError::BadSignature);
         }
         let mut chunks: Vec<Chunk> = Vec::new();
         while index < array.len() {
+            let length_bytes = array
+                .get(index..index + 4)
+                .ok_or(PngError::TruncatedChunk { offset: index })?;
             let chunk_len = u32::from_be_bytes([
-                array[index],
-                array[index + 1],
-                array[index + 2],
-                array[index + 3],
+                length_bytes[0],
+                length_bytes[1],
+                length_bytes[2],
+                length_bytes[3],
             ]);
+            if chunk_len > Chunk::MAX_LENGTH {
+                return Err(PngError::ChunkTooLong {
+                    offset: index,
+                    length: chunk_len,
+                });
+            }
             let end_range = index + chunk_len as usize + Chunk::SIZE_WITHOUT_DATA;
-            let chunk = Chunk::try_from(&array[index..end_range])?;
+            let chunk_bytes = array
+                .get(index..end_range)
+                .ok_or(PngError::TruncatedChunk { offset: index })?;
+            let chunk = Chunk::try_from(chunk_bytes).map_err(|e| e.at_offset(index))?;
+            let end = chunk.chunk_type().bytes() == *b"IEND";
             chunks.push(chunk);
-            index += chunk_len as usize + 12; // 3 * 4-bytes data not included in chunk_len
+            index = end_range;
+            // what follows IEND is not made of chunks
+            if end {
+                break;
+            }
         }
         log::info!("nombre de chunks de ce png : {}", chunks.len());
-        let png: Png = Png::from_chunks(chunks);
+        let mut png: Png = Png::from_chunks(chunks);
+        png.trailer = array[index..].to_vec();
         Ok(png)
     }
 }
@@ -140,13 +212,11 @@ mod tests {
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -165,7 +235,7 @@ mod tests {
         let chunks = testing_chunks();
         let png = Png::from_chunks(chunks);
 
-        assert_eq!(png._chunks().len(), 3);
+        assert_eq!(png.chunks().len(), 3);
     }
 
     #[test]
@@ -201,7 +271,7 @@ mod tests {
 
         let png = Png::try_from(bytes.as_ref());
 
-        assert!(png.is_err());
+        assert!(matches!(png, Err(PngError::BadSignature)));
     }
 
     #[test]
@@ -229,7 +299,7 @@ mod tests {
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
-        let chunks = png._chunks();
+        let chunks = png.chunks();
         assert_eq!(chunks.len(), 3);
     }
 
@@ -241,6 +311,16 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am another chunk").unwrap());
+        let chunks: Vec<&Chunk> = png.chunks_by_type("FrSt").collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[1].data_as_string().unwrap(), "I am another chunk");
+        assert_eq!(png.chunks_by_type("otHr").count(), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -250,6 +330,18 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_append_chunk_to_few_chunks() {
+        let mut png = Png::from_chunks(Vec::new());
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        assert_eq!(png.chunks().len(), 1);
+
+        let mut png = Png::from_chunks(vec![chunk_from_strings("IEND", "").unwrap()]);
+        png.append_chunk(chunk_from_strings("TeSt", "before").unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["TeSt", "IEND"]);
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -265,6 +357,43 @@ mod tests {
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_png_from_file_full_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dice.png");
+        std::fs::write(&path, PNG_FILE).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_png_from_reader() {
+        let png = Png::from_reader(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_write_to() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut actual: Vec<u8> = Vec::new();
+        png.write_to(&mut actual).unwrap();
+        assert_eq!(actual, PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_header_info() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.header_info().unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 50);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), crate::ColorType::Rgba);
+
+        let png = testing_png();
+        assert!(matches!(png.header_info(), Err(PngError::ChunkNotFound(_))));
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
@@ -291,6 +420,70 @@ mod tests {
         let _png_string = format!("{}", png);
     }
 
+    #[test]
+    fn test_trailer() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"\0\0\0\x05 not a chunk");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(png.trailer(), b"\0\0\0\x05 not a chunk");
+        assert_eq!(png.as_bytes(), bytes);
+
+        let mut png = Png::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailer(), b"\0\0\0\x05 not a chunk");
+        png.set_trailer(b"other".to_vec());
+        assert_eq!(png.as_bytes()[PNG_FILE.len()..], *b"other");
+        assert_eq!(png.remove_trailer(), b"other");
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+        assert!(testing_png().trailer().is_empty());
+    }
+
+    #[test]
+    fn test_shorter_than_signature() {
+        let png = Png::try_from(&PNG_FILE[..5]);
+        assert!(matches!(png, Err(PngError::BadSignature)));
+
+        let png = Png::try_from(&[][..]);
+        assert!(matches!(png, Err(PngError::BadSignature)));
+    }
+
+    #[test]
+    fn test_only_signature() {
+        let png = Png::try_from(&Png::STANDARD_HEADER[..]).unwrap();
+        assert!(png.chunks().is_empty());
+    }
+
+    #[test]
+    fn test_truncated_png() {
+        // the IHDR chunk goes from offset 8 to 33, the next chunk starts at 33
+        let png = Png::try_from(&PNG_FILE[..10]);
+        assert!(matches!(png, Err(PngError::TruncatedChunk { offset: 8 })));
+
+        let png = Png::try_from(&PNG_FILE[..40]);
+        assert!(matches!(png, Err(PngError::TruncatedChunk { offset: 33 })));
+
+        // cutting the file anywhere must never panic
+        for len in 0..PNG_FILE.len() {
+            let _ = Png::try_from(&PNG_FILE[..len]);
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_above_limit() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let png = Png::try_from(bytes.as_ref());
+        assert!(matches!(png, Err(PngError::ChunkTooLong { offset: 8, .. })));
+    }
+
+    #[test]
+    fn test_crc_error_offset() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[32] ^= 0xff;
+        let png = Png::try_from(bytes.as_ref());
+        assert!(matches!(png, Err(PngError::CrcMismatch { offset: 8, .. })));
+    }
+
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
     const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
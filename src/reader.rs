@@ -0,0 +1,404 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{PngError, Result};
+use std::io::{self, Read, Write};
+
+/// Reads the chunks of a PNG one at a time from any `Read`.
+/// The signature is checked when the reader is created, then each chunk is
+/// read and its CRC validated only when it is requested, so the whole image
+/// is never held in memory. Reading stops at `IEND` : what follows is the
+/// trailer, given by [`read_trailer`](PngReader::read_trailer).
+///
+/// ```no_run
+/// use pngme::PngReader;
+/// use std::fs::File;
+///
+/// let reader = PngReader::new(File::open("file.png")?)?;
+/// for chunk in reader {
+///     let chunk = chunk?;
+///     println!("{} {}", chunk.chunk_type(), chunk.length());
+/// }
+/// # Ok::<(), pngme::Error>(())
+/// ```
+pub struct PngReader<R: Read> {
+    reader: R,
+    offset: usize,
+    done: bool,
+    /// `IEND` was read, the chunks are over
+    ended: bool,
+    pending: Option<ChunkHeader>,
+}
+
+/// Length and type of a chunk, read before its data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+    offset: usize,
+    length: u32,
+    chunk_type: ChunkType,
+}
+
+impl ChunkHeader {
+    /// Byte offset of the chunk from the start of the input
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Length of the data, in bytes
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    fn as_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&self.length.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.chunk_type.bytes());
+        bytes
+    }
+
+    /// Number of bytes after the header : data and CRC
+    fn body_length(&self) -> u64 {
+        self.length as u64 + 4
+    }
+}
+
+impl<R: Read> PngReader<R> {
+    /// Read and check the PNG signature
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(PngError::BadSignature)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if header != Png::STANDARD_HEADER {
+            return Err(PngError::BadSignature);
+        }
+        Ok(PngReader {
+            reader,
+            offset: Png::STANDARD_HEADER.len(),
+            done: false,
+            ended: false,
+            pending: None,
+        })
+    }
+
+    /// Byte offset, from the start of the input, of the next chunk to read
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Give back the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the next chunk, `None` at the end of the input
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        match self.next_header()? {
+            Some(_) => self.read_body().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read only the length and type of the next chunk, `None` after `IEND` or
+    /// at the end of the input. The body must then be consumed with [`read_body`](Self::read_body),
+    /// [`copy_body`](Self::copy_body) or [`skip_body`](Self::skip_body), otherwise
+    /// it is skipped by the next call.
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+        if self.pending.is_some() {
+            self.skip_body()?;
+        }
+        if self.done || self.ended {
+            return Ok(None);
+        }
+        let result = self.read_header();
+        match &result {
+            Ok(Some(header)) => {
+                self.ended = header.chunk_type.bytes() == *b"IEND";
+                self.pending = Some(header.clone());
+            }
+            _ => self.done = true,
+        }
+        result
+    }
+
+    /// Read the bytes after `IEND`, once every chunk was read. Empty if there
+    /// are none, or if the input has no `IEND`.
+    pub fn read_trailer(&mut self) -> Result<Vec<u8>> {
+        let mut trailer = Vec::new();
+        self.copy_trailer(&mut trailer)?;
+        Ok(trailer)
+    }
+
+    /// Copy the bytes after `IEND` to `writer`, like [`read_trailer`](Self::read_trailer),
+    /// and return their number
+    pub fn copy_trailer<W: Write>(&mut self, writer: &mut W) -> Result<u64> {
+        if self.pending.is_some() {
+            self.skip_body()?;
+        }
+        if !self.ended || self.done {
+            return Ok(0);
+        }
+        self.done = true;
+        let copied = io::copy(&mut self.reader, writer)?;
+        self.offset += copied as usize;
+        Ok(copied)
+    }
+
+    /// Read the data and CRC of the chunk whose header was just read, and
+    /// check the CRC
+    pub fn read_body(&mut self) -> Result<Chunk> {
+        let header = self.take_pending()?;
+        // the buffer grows while reading, a lying length can't make us allocate 2 GB
+        let mut bytes = header.as_bytes().to_vec();
+        (&mut self.reader)
+            .take(header.body_length())
+            .read_to_end(&mut bytes)
+            .map_err(|e| self.fail(&header, e))?;
+        if bytes.len() != header.length as usize + Chunk::SIZE_WITHOUT_DATA {
+            return Err(self.fail_truncated(&header));
+        }
+        let chunk = Chunk::try_from(bytes.as_slice()).map_err(|e| {
+            self.done = true;
+            e.at_offset(header.offset)
+        })?;
+        self.offset += bytes.len();
+        Ok(chunk)
+    }
+
+    /// Copy the whole chunk whose header was just read to `writer`, byte for
+    /// byte and without checking its CRC
+    pub fn copy_body<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        let header = self.take_pending()?;
+        writer.write_all(&header.as_bytes())?;
+        self.forward_body(&header, writer)
+    }
+
+    /// Skip the data and CRC of the chunk whose header was just read
+    pub fn skip_body(&mut self) -> Result<()> {
+        let header = self.take_pending()?;
+        self.forward_body(&header, &mut io::sink())
+    }
+
+    fn forward_body<W: Write>(&mut self, header: &ChunkHeader, writer: &mut W) -> Result<()> {
+        let copied = io::copy(&mut (&mut self.reader).take(header.body_length()), writer)
+            .map_err(|e| self.fail(header, e))?;
+        if copied != header.body_length() {
+            return Err(self.fail_truncated(header));
+        }
+        self.offset += header.length as usize + Chunk::SIZE_WITHOUT_DATA;
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> Result<Option<ChunkHeader>> {
+        let start = self.offset;
+        let mut bytes = [0u8; 8];
+        let read = read_up_to(&mut self.reader, &mut bytes)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < bytes.len() {
+            return Err(PngError::TruncatedChunk { offset: start });
+        }
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if length > Chunk::MAX_LENGTH {
+            return Err(PngError::ChunkTooLong {
+                offset: start,
+                length,
+            });
+        }
+        let chunk_type = ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])
+            .map_err(|e| e.at_offset(start + 4))?;
+        Ok(Some(ChunkHeader {
+            offset: start,
+            length,
+            chunk_type,
+        }))
+    }
+
+    fn take_pending(&mut self) -> Result<ChunkHeader> {
+        self.pending.take().ok_or_else(|| {
+            PngError::Io(io::Error::other("no chunk header read before its body"))
+        })
+    }
+
+    fn fail(&mut self, header: &ChunkHeader, e: io::Error) -> PngError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            return self.fail_truncated(header);
+        }
+        self.done = true;
+        PngError::Io(e)
+    }
+
+    fn fail_truncated(&mut self, header: &ChunkHeader) -> PngError {
+        self.done = true;
+        PngError::TruncatedChunk {
+            offset: header.offset,
+        }
+    }
+}
+
+impl<R: Read> Iterator for PngReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+/// Fill `buf` as much as possible, returning less only at the end of the input
+pub(crate) fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = [
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"middle".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), Vec::new()),
+        ];
+        Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|c| c.as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let bytes = testing_bytes();
+        let reader = PngReader::new(bytes.as_slice()).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_>>().unwrap();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(chunks.as_slice(), png.chunks());
+    }
+
+    #[test]
+    fn test_offset() {
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.offset(), 8);
+        reader.next_chunk().unwrap();
+        assert_eq!(reader.offset(), 8 + 12 + 5);
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 0;
+        assert!(matches!(
+            PngReader::new(bytes.as_slice()),
+            Err(PngError::BadSignature)
+        ));
+        assert!(matches!(
+            PngReader::new(&bytes[..4]),
+            Err(PngError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(&bytes[..bytes.len() - 2]).unwrap();
+        assert!(reader.next_chunk().unwrap().is_some());
+        assert!(reader.next_chunk().unwrap().is_some());
+        assert!(matches!(
+            reader.next_chunk(),
+            Err(PngError::TruncatedChunk { offset: 43 })
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_headers_and_bodies() {
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+
+        let header = reader.next_header().unwrap().unwrap();
+        assert_eq!(header.chunk_type().to_string(), "FrSt");
+        assert_eq!(header.offset(), 8);
+        assert_eq!(header.length(), 5);
+        let mut copied = Vec::new();
+        reader.copy_body(&mut copied).unwrap();
+        assert_eq!(copied, bytes[8..25].to_vec());
+
+        // the body of miDl is skipped by the next call
+        let header = reader.next_header().unwrap().unwrap();
+        assert_eq!(header.chunk_type().to_string(), "miDl");
+        let header = reader.next_header().unwrap().unwrap();
+        assert_eq!(header.chunk_type().to_string(), "LASt");
+        assert_eq!(header.offset(), 43);
+        let chunk = reader.read_body().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "LASt");
+        assert!(reader.next_header().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_copy_body_keeps_bad_crc() {
+        let mut bytes = testing_bytes();
+        bytes[24] ^= 0xff;
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        reader.next_header().unwrap();
+        let mut copied = Vec::new();
+        reader.copy_body(&mut copied).unwrap();
+        assert_eq!(copied, bytes[8..25].to_vec());
+    }
+
+    #[test]
+    fn test_crc_error() {
+        let mut bytes = testing_bytes();
+        bytes[20] ^= 0xff;
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next_chunk(),
+            Err(PngError::CrcMismatch { offset: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_trailer() {
+        let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(&iend.as_bytes());
+        bytes.extend_from_slice(b"not a chunk");
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        let types: Vec<String> = (&mut reader)
+            .map(|c| c.unwrap().chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["FrSt", "miDl", "LASt", "IEND"]);
+        assert_eq!(reader.offset(), bytes.len() - 11);
+        assert_eq!(reader.read_trailer().unwrap(), b"not a chunk");
+        assert_eq!(reader.offset(), bytes.len());
+
+        // the body of IEND is skipped, and there is no trailer without IEND
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        while reader.next_header().unwrap().is_some() {}
+        assert_eq!(reader.read_trailer().unwrap(), b"not a chunk");
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.by_ref().count(), 3);
+        assert!(reader.read_trailer().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,668 @@
+use crate::chunk::Chunk;
+use crate::idat;
+use crate::lsb;
+use crate::pixels::{self, Pixels};
+use crate::png::Png;
+use crate::reader::PngReader;
+use crate::Result;
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::Read;
+
+/// Ancillary chunks of the spec and of its registered extensions, APNG included
+const KNOWN_ANCILLARY_CHUNKS: [&[u8; 4]; 29] = [
+    b"bKGD", b"cHRM", b"cICP", b"cLLI", b"dSIG", b"eXIf", b"gAMA", b"gIFg", b"gIFt", b"gIFx",
+    b"hIST", b"iCCP", b"iTXt", b"mDCV", b"oFFs", b"pCAL", b"pHYs", b"sBIT", b"sCAL", b"sPLT",
+    b"sRGB", b"sTER", b"tEXt", b"tIME", b"tRNS", b"zTXt", b"acTL", b"fcTL", b"fdAT",
+];
+/// Chunks holding compressed data, whose entropy is high anyway
+const COMPRESSED_CHUNKS: [&[u8; 4]; 5] = [b"zTXt", b"iTXt", b"iCCP", b"fdAT", b"IDAT"];
+/// First bytes of the data hidden by pngme
+const PNGME_MAGICS: [&[u8; 5]; 4] = [
+    &crate::payload::MAGIC,
+    &crate::crypto::MAGIC,
+    &crate::split::MAGIC,
+    &crate::trailer::MAGIC,
+];
+
+/// Text chunks larger than this are unusual for metadata
+const LARGE_TEXT: usize = 2048;
+/// Chunks shorter than this don't give a meaningful entropy
+const MIN_ENTROPY_LEN: usize = 64;
+/// Entropy, in bits per byte, above which data looks compressed or encrypted
+const HIGH_ENTROPY: f64 = 7.5;
+/// Probability of the chi-square test above which the LSBs look replaced
+const CHI_SQUARE_LIMIT: f64 = 0.95;
+/// Share of the samples carrying data, estimated by RS, above which the
+/// image is flagged
+const RS_LIMIT: f64 = 0.05;
+/// Largest image data, in bytes of scanlines, whose LSBs are tested : the
+/// tests keep several copies of the samples in memory
+const MAX_TESTED_LEN: usize = 64 * 1024 * 1024;
+
+/// Overall verdict of a scan, from its score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Score below 20
+    Clean,
+    /// Score from 20 to 59
+    Suspicious,
+    /// Score of 60 or more
+    LikelyHidden,
+}
+
+impl Verdict {
+    fn from_score(score: u8) -> Verdict {
+        match score {
+            0..=19 => Verdict::Clean,
+            20..=59 => Verdict::Suspicious,
+            _ => Verdict::LikelyHidden,
+        }
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Clean => write!(f, "clean"),
+            Verdict::Suspicious => write!(f, "suspicious"),
+            Verdict::LikelyHidden => write!(f, "likely hidden data"),
+        }
+    }
+}
+
+/// One artefact found by [`scan`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suspicion {
+    /// Byte offset of the chunk or data at fault, `None` for the statistical tests
+    pub offset: Option<usize>,
+    /// Short identifier of the kind of artefact, e.g. `trailing-data`
+    pub code: &'static str,
+    /// How much the artefact points to hidden data, from 0 to 100
+    pub score: u8,
+    pub message: String,
+}
+
+impl Display for Suspicion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let offset = self.offset.map_or("-".to_string(), |o| o.to_string());
+        write!(f, "{:>3} offset {:>8} : {}", self.score, offset, self.message)
+    }
+}
+
+/// Result of the statistical tests on the pixel LSBs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LsbStatistics {
+    /// Probability that the LSBs of the colour samples were replaced, from
+    /// the chi-square test on the whole image and on its first eighth, `None`
+    /// if the histogram is too smooth for the test
+    pub chi_square: Option<f64>,
+    /// Share of the samples whose LSB carries data, estimated by RS analysis
+    pub rs_estimate: Option<f64>,
+}
+
+/// Scored report of a [`scan`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScanReport {
+    /// Suspicion that the image hides data, from 0 to 100
+    pub score: u8,
+    pub verdict: Verdict,
+    pub suspicions: Vec<Suspicion>,
+    /// `None` if the pixels couldn't be tested
+    pub statistics: Option<LsbStatistics>,
+    /// Why the pixels were not tested
+    pub statistics_skipped: Option<String>,
+}
+
+/// Look for the artefacts left by steganography tools : private or unknown
+/// ancillary chunks, chunks with a high entropy or starting like pngme data,
+/// data after IEND, large text chunks, bytes after the zlib stream of the
+/// IDAT chunks, and LSBs of the pixels failing the chi-square and RS tests.
+///
+/// Each artefact gets a score, and the score of the image is the probability
+/// that at least one of them is due to hidden data, as if they were
+/// independent. Returns `Err` if the PNG can't be parsed up to IEND.
+pub fn scan<R: Read>(reader: R) -> Result<ScanReport> {
+    let mut chunks = Vec::new();
+    let mut offsets = Vec::new();
+    let mut reader = PngReader::new(reader)?;
+    while let Some(header) = reader.next_header()? {
+        offsets.push(header.offset());
+        chunks.push(reader.read_body()?);
+    }
+    let trailer_offset = reader.offset();
+    let mut png = Png::from_chunks(chunks);
+    png.set_trailer(reader.read_trailer()?);
+    let trailer = png.trailer();
+
+    let mut suspicions = Vec::new();
+    for (chunk, &offset) in png.chunks().iter().zip(&offsets) {
+        scan_chunk(chunk, offset, &mut suspicions);
+    }
+    if !trailer.is_empty() {
+        suspicions.push(Suspicion {
+            offset: Some(trailer_offset),
+            code: "trailing-data",
+            score: 70,
+            message: format!(
+                "{} bytes after IEND, entropy {:.2} bits per byte",
+                trailer.len(),
+                entropy(trailer)
+            ),
+        });
+        if PNGME_MAGICS.iter().any(|magic| trailer.starts_with(*magic)) {
+            suspicions.push(Suspicion {
+                offset: Some(trailer_offset),
+                code: "pngme-data",
+                score: 90,
+                message: "the data after IEND starts like data hidden by pngme".to_string(),
+            });
+        }
+    }
+    if let Some(s) = scan_zlib_trailer(&png, &offsets) {
+        suspicions.push(s);
+    }
+
+    let (statistics, statistics_skipped) = match lsb_statistics(&png) {
+        Ok(statistics) => (statistics, None),
+        Err(reason) => (None, Some(reason)),
+    };
+    if let Some(statistics) = &statistics {
+        if let Some(chi_square) = statistics.chi_square.filter(|&p| p >= CHI_SQUARE_LIMIT) {
+            suspicions.push(Suspicion {
+                offset: None,
+                code: "chi-square",
+                score: 60,
+                message: format!(
+                    "chi-square test : the pairs of values differing by their LSB are \
+                     even, probability of embedding {:.3}",
+                    chi_square
+                ),
+            });
+        }
+        if let Some(estimate) = statistics.rs_estimate.filter(|&e| e >= RS_LIMIT) {
+            suspicions.push(Suspicion {
+                offset: None,
+                code: "rs-analysis",
+                score: (30.0 + 120.0 * estimate).min(90.0) as u8,
+                message: format!(
+                    "RS analysis : about {:.0}% of the colour samples carry data in their LSB",
+                    estimate * 100.0
+                ),
+            });
+        }
+    }
+
+    let clean = suspicions
+        .iter()
+        .fold(1.0, |clean, s| clean * (1.0 - s.score as f64 / 100.0));
+    let score = ((1.0 - clean) * 100.0).round() as u8;
+    Ok(ScanReport {
+        score,
+        verdict: Verdict::from_score(score),
+        suspicions,
+        statistics,
+        statistics_skipped,
+    })
+}
+
+/// Checks on the type and data of one chunk
+fn scan_chunk(chunk: &Chunk, offset: usize, suspicions: &mut Vec<Suspicion>) {
+    let chunk_type = chunk.chunk_type();
+    let bytes = chunk_type.bytes();
+    let data = chunk.data();
+    if !chunk_type.is_critical() {
+        let (code, score, message) = if bytes == crate::signature::CHUNK_TYPE {
+            ("pngme-signature", 20, "pngme signature chunk".to_string())
+        } else if !chunk_type.is_public() {
+            ("private-chunk", 40, format!("private ancillary chunk {}", chunk_type))
+        } else if !KNOWN_ANCILLARY_CHUNKS.contains(&&bytes) {
+            ("unknown-chunk", 30, format!("unknown public ancillary chunk {}", chunk_type))
+        } else {
+            ("", 0, String::new())
+        };
+        if score > 0 {
+            suspicions.push(Suspicion {
+                offset: Some(offset),
+                code,
+                score,
+                message,
+            });
+        }
+    }
+    if PNGME_MAGICS.iter().any(|magic| data.starts_with(*magic)) {
+        suspicions.push(Suspicion {
+            offset: Some(offset),
+            code: "pngme-data",
+            score: 90,
+            message: format!("chunk {} starts like data hidden by pngme", chunk_type),
+        });
+    } else if !COMPRESSED_CHUNKS.contains(&&bytes) && data.len() >= MIN_ENTROPY_LEN {
+        let entropy = entropy(data);
+        if entropy >= HIGH_ENTROPY {
+            suspicions.push(Suspicion {
+                offset: Some(offset),
+                code: "high-entropy",
+                score: 50,
+                message: format!(
+                    "{} bytes of chunk {} with an entropy of {:.2} bits per byte, \
+                     compressed or encrypted",
+                    data.len(),
+                    chunk_type,
+                    entropy
+                ),
+            });
+        }
+    }
+    if [*b"tEXt", *b"zTXt", *b"iTXt"].contains(&bytes) && data.len() > LARGE_TEXT {
+        suspicions.push(Suspicion {
+            offset: Some(offset),
+            code: "large-text",
+            score: 30,
+            message: format!("text chunk {} of {} bytes", chunk_type, data.len()),
+        });
+    }
+}
+
+/// Bytes left in the IDAT chunks after the end of their zlib stream
+fn scan_zlib_trailer(png: &Png, offsets: &[usize]) -> Option<Suspicion> {
+    let compressed = idat::concat(png).ok()?;
+    let slack = &compressed[idat::stream_len(&compressed).ok()?..];
+    if slack.is_empty() {
+        return None;
+    }
+    let pngme = slack.starts_with(&crate::slack::MAGIC);
+    let first_idat = png
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type().bytes() == *b"IDAT")
+        .map(|i| offsets[i]);
+    Some(Suspicion {
+        offset: first_idat,
+        code: "zlib-trailing-data",
+        score: if pngme { 90 } else { 80 },
+        message: format!(
+            "{} bytes after the end of the zlib stream of IDAT{}",
+            slack.len(),
+            if pngme { ", starting like data hidden by pngme" } else { "" }
+        ),
+    })
+}
+
+/// Shannon entropy of `data`, in bits per byte
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Chi-square and RS tests on the colour samples, `None` if the image is too
+/// small for them. Returns why they were not run if the image can't be decoded
+/// or is larger than [`MAX_TESTED_LEN`], checked from the IHDR before decoding.
+fn lsb_statistics(png: &Png) -> std::result::Result<Option<LsbStatistics>, String> {
+    let len = png
+        .header_info()
+        .and_then(|ihdr| pixels::decoded_len(&ihdr))
+        .map_err(|e| e.to_string())?;
+    if len > MAX_TESTED_LEN {
+        return Err(format!(
+            "image too large to test : {} bytes of scanlines, {} at most",
+            len, MAX_TESTED_LEN
+        ));
+    }
+    let pixels = Pixels::from_png(png).map_err(|e| e.to_string())?;
+    let channels = lsb::color_samples(&pixels).map_err(|e| e.to_string())?;
+    let count = channels.iter().map(|c| c.len()).sum::<usize>();
+    if count < 64 {
+        return Ok(None);
+    }
+    // samples in the order lsb::embed uses them, whose start is filled first
+    let colors = channels.len();
+    let interleaved: Vec<u8> = (0..count).map(|i| channels[i % colors][i / colors]).collect();
+    let chi_square = [&interleaved[..], &interleaved[..count / 8]]
+        .iter()
+        .filter_map(|samples| chi_square(samples))
+        .reduce(f64::max);
+    Ok(Some(LsbStatistics {
+        chi_square,
+        rs_estimate: rs_estimate(&channels),
+    }))
+}
+
+/// Probability that the LSBs of `samples` were replaced by random bits, from
+/// the chi-square test of Westfeld and Pfitzmann : embedding makes the counts
+/// of the values 2k and 2k + 1 equal. `None` if the counts of 2k + 1 and
+/// 2k + 2 are as equal, a smooth histogram the test can't tell from embedding.
+fn chi_square(samples: &[u8]) -> Option<f64> {
+    let mut histogram = [0u64; 256];
+    for &s in samples {
+        histogram[s as usize] += 1;
+    }
+    let shifted = pairs_probability(&histogram[1..255])?;
+    if shifted >= 0.5 {
+        return None;
+    }
+    pairs_probability(&histogram)
+}
+
+/// Probability that the counts of the successive pairs of `histogram` are
+/// equal, by the chi-square law
+fn pairs_probability(histogram: &[u64]) -> Option<f64> {
+    let mut chi = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        // too few values for the approximation by the chi-square law
+        if expected < 5.0 {
+            continue;
+        }
+        chi += (pair[0] as f64 - expected).powi(2) / expected;
+        categories += 1;
+    }
+    if categories < 2 {
+        return None;
+    }
+    Some(1.0 - gamma_p((categories - 1) as f64 / 2.0, chi / 2.0))
+}
+
+/// Share of the samples whose LSB was replaced, estimated by the RS analysis
+/// of Fridrich, Goljan and Du on groups of 4 neighbour samples of a channel
+fn rs_estimate(channels: &[Vec<u8>]) -> Option<f64> {
+    let (d0, e0) = rs_counts(channels, false)?;
+    let (d1, e1) = rs_counts(channels, true)?;
+    let a = 2.0 * (d1 + d0);
+    let b = e0 - e1 - d1 - 3.0 * d0;
+    let c = d0 - e0;
+    let x = if a.abs() < 1e-9 {
+        if b.abs() < 1e-9 {
+            return None;
+        }
+        -c / b
+    } else {
+        let delta = b * b - 4.0 * a * c;
+        // RM and SM meet when every LSB carries data, and the root goes to infinity
+        if delta < 0.0 {
+            return Some(1.0);
+        }
+        let roots = [(-b + delta.sqrt()) / (2.0 * a), (-b - delta.sqrt()) / (2.0 * a)];
+        if roots[0].abs() < roots[1].abs() {
+            roots[0]
+        } else {
+            roots[1]
+        }
+    };
+    Some((x / (x - 0.5)).clamp(0.0, 1.0))
+}
+
+/// Share of regular minus share of singular groups for the mask flipping the
+/// LSB and for the mask shifting it, with every LSB flipped first if `flipped`
+fn rs_counts(channels: &[Vec<u8>], flipped: bool) -> Option<(f64, f64)> {
+    const MASK: [bool; 4] = [false, true, true, false];
+    let smoothness = |g: &[i16; 4]| -> i32 {
+        g.windows(2).map(|w| (w[1] - w[0]).abs() as i32).sum()
+    };
+    let apply = |g: &[i16; 4], f: fn(i16) -> i16| -> [i16; 4] {
+        let mut out = *g;
+        for (v, &m) in out.iter_mut().zip(&MASK) {
+            if m {
+                *v = f(*v);
+            }
+        }
+        out
+    };
+    let flip = |v: i16| v ^ 1;
+    let shift = |v: i16| ((v + 1) ^ 1) - 1;
+    let (mut regular, mut singular, mut regular_neg, mut singular_neg, mut groups) =
+        (0, 0, 0, 0, 0);
+    for channel in channels {
+        for group in channel.chunks_exact(4) {
+            let mut g = [0i16; 4];
+            for (v, &s) in g.iter_mut().zip(group) {
+                *v = if flipped { (s ^ 1) as i16 } else { s as i16 };
+            }
+            let f = smoothness(&g);
+            match smoothness(&apply(&g, flip)).cmp(&f) {
+                std::cmp::Ordering::Greater => regular += 1,
+                std::cmp::Ordering::Less => singular += 1,
+                std::cmp::Ordering::Equal => (),
+            }
+            match smoothness(&apply(&g, shift)).cmp(&f) {
+                std::cmp::Ordering::Greater => regular_neg += 1,
+                std::cmp::Ordering::Less => singular_neg += 1,
+                std::cmp::Ordering::Equal => (),
+            }
+            groups += 1;
+        }
+    }
+    if groups == 0 {
+        return None;
+    }
+    let groups = groups as f64;
+    Some((
+        (regular - singular) as f64 / groups,
+        (regular_neg - singular_neg) as f64 / groups,
+    ))
+}
+
+/// Regularized lower incomplete gamma function P(a, x), the CDF of the
+/// chi-square law with 2a degrees of freedom at 2x
+fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        // series
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (sum * log_prefix.exp()).min(1.0)
+    } else {
+        // continued fraction of Lentz for Q(a, x)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = b + an / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (1.0 - log_prefix.exp() * h).max(0.0)
+    }
+}
+
+/// Logarithm of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    /// A smooth RGB image, like a photo : a gradient with a little noise
+    fn photo(width: u32, height: u32) -> Png {
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        let mut raw = Vec::new();
+        let mut noise = 7u32;
+        for y in 0..height {
+            raw.push(0);
+            for x in 0..width {
+                for channel in 0..3 {
+                    noise = noise.wrapping_mul(1103515245).wrapping_add(12345);
+                    let (x, y) = (x as f64, (y + channel * 10) as f64);
+                    let base = 128.0 + 100.0 * (x / 9.0).sin() * (y / 7.0).cos();
+                    let noise = ((noise >> 16) % 3) as f64 + ((noise >> 24) % 3) as f64 - 2.0;
+                    raw.push((base + noise).round() as u8);
+                }
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&raw).unwrap();
+        Png::from_chunks(vec![
+            chunk("IHDR", &ihdr),
+            chunk("tEXt", b"Comment\0holiday"),
+            chunk("IDAT", &encoder.finish().unwrap()),
+            chunk("IEND", b""),
+        ])
+    }
+
+    fn codes(report: &ScanReport) -> Vec<&'static str> {
+        report.suspicions.iter().map(|s| s.code).collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = scan(photo(64, 64).as_bytes().as_slice()).unwrap();
+        assert_eq!(codes(&report), Vec::<&str>::new(), "{:?}", report);
+        assert_eq!((report.score, report.verdict), (0, Verdict::Clean));
+        let statistics = report.statistics.unwrap();
+        assert!(statistics.chi_square.is_none(), "{:?}", statistics);
+        assert!(statistics.rs_estimate.unwrap() < RS_LIMIT, "{:?}", statistics);
+    }
+
+    #[test]
+    fn test_chunk_artefacts() {
+        let mut png = photo(16, 16);
+        png.append_chunk(chunk("ruSt", b"a message"));
+        png.append_chunk(chunk("aBCd", &[0; 10]));
+        let random: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        png.append_chunk(chunk("prIv", &random));
+        png.append_chunk(chunk("tEXt", &[b'a'; 3000]));
+        png.append_chunk(chunk("ruSt", b"PNGMEencrypted"));
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"appended");
+
+        let report = scan(bytes.as_slice()).unwrap();
+        assert_eq!(
+            codes(&report),
+            [
+                "private-chunk",
+                "unknown-chunk",
+                "private-chunk",
+                "high-entropy",
+                "large-text",
+                "private-chunk",
+                "pngme-data",
+                "trailing-data"
+            ]
+        );
+        assert_eq!(report.suspicions.last().unwrap().offset, Some(bytes.len() - 8));
+        assert_eq!(report.verdict, Verdict::LikelyHidden);
+
+        let mut png = photo(16, 16);
+        png.set_trailer(b"PNGMPpayload".to_vec());
+        let report = scan(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(codes(&report), ["trailing-data", "pngme-data"]);
+    }
+
+    #[test]
+    fn test_zlib_trailing_data() {
+        let png = photo(16, 16);
+        let mut chunks = png.chunks().to_vec();
+        let mut idat = chunks[2].data().to_vec();
+        idat.extend_from_slice(b"slack");
+        chunks[2] = chunk("IDAT", &idat);
+        let report = scan(Png::from_chunks(chunks).as_bytes().as_slice()).unwrap();
+        assert_eq!(codes(&report), ["zlib-trailing-data"]);
+        assert_eq!(report.suspicions[0].score, 80);
+
+        let embedded = crate::slack::embed(&png, b"message").unwrap();
+        let report = scan(embedded.as_bytes().as_slice()).unwrap();
+        assert_eq!(codes(&report), ["zlib-trailing-data"]);
+        assert_eq!(report.suspicions[0].score, 90);
+    }
+
+    #[test]
+    fn test_lsb_embedding() {
+        let png = photo(64, 64);
+        let capacity = lsb::capacity(&png, 1).unwrap();
+        let data: Vec<u8> = (0..capacity as u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 11) as u8)
+            .collect();
+        let embedded = lsb::embed(&png, &data, Some("key"), 1).unwrap();
+        let report = scan(embedded.as_bytes().as_slice()).unwrap();
+        assert_eq!(codes(&report), ["chi-square", "rs-analysis"], "{:?}", report.statistics);
+        assert_eq!(report.verdict, Verdict::LikelyHidden);
+
+        // a short message embedded in order is seen by the test on the first eighth
+        let embedded = lsb::embed(&png, &data[..capacity / 8], None, 1).unwrap();
+        let report = scan(embedded.as_bytes().as_slice()).unwrap();
+        assert!(codes(&report).contains(&"chi-square"), "{:?}", report);
+    }
+
+    #[test]
+    fn test_oversized_header() {
+        let png = photo(16, 16);
+        let mut ihdr = 100_000u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&100_000u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        let mut chunks = png.chunks().to_vec();
+        chunks[0] = chunk("IHDR", &ihdr);
+        let report = scan(Png::from_chunks(chunks).as_bytes().as_slice()).unwrap();
+        assert_eq!(report.statistics, None);
+        assert!(report.statistics_skipped.unwrap().starts_with("image too large to test"));
+    }
+
+    #[test]
+    fn test_gamma_p() {
+        // chi-square law with 2 degrees of freedom : 1 - e^(-x/2)
+        for x in [0.5f64, 2.0, 7.0] {
+            assert!((gamma_p(1.0, x / 2.0) - (1.0 - (-x / 2.0).exp())).abs() < 1e-9);
+        }
+        assert!((gamma_p(5.0, 20.0) - 0.999983).abs() < 1e-5);
+    }
+}
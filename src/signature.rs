@@ -0,0 +1,431 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto::{from_hex, parse_key_file, random_bytes, to_hex};
+use crate::png::Png;
+use crate::reader::PngReader;
+use crate::{PngError, Result};
+use ed25519_dalek::Signer;
+use std::fmt::Display;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+/// Type of the chunk holding the signature : ancillary, private, and unsafe to
+/// copy since it depends on the critical chunks
+pub const CHUNK_TYPE: [u8; 4] = *b"siGN";
+/// Version of the layout of the signature chunk
+pub const VERSION: u8 = 1;
+const ALGORITHM_ED25519: u8 = 1;
+/// Prefix of the signed bytes, so that they can't be mistaken for anything else
+const DOMAIN: &[u8] = b"pngme signature v1\0";
+const PUBLIC_KEY_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+
+/// Ed25519 secret key signing images
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+/// Ed25519 public key checking the signatures, written as 64 hex digits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+/// What a valid signature covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signed {
+    /// Key of the signer
+    pub signer: VerifyingKey,
+    /// Ancillary chunk types covered besides the critical chunks
+    pub ancillary: Vec<ChunkType>,
+    /// Number of chunks covered
+    pub covered: usize,
+    /// Bytes after `IEND`, which no signature covers : they may have been
+    /// added or changed since the image was signed
+    pub unsigned_trailer: usize,
+}
+
+impl SigningKey {
+    /// A new random key pair
+    pub fn generate() -> Result<SigningKey> {
+        let mut bytes = [0u8; PUBLIC_KEY_LEN];
+        random_bytes(&mut bytes)?;
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&bytes)))
+    }
+
+    /// The public key to give to the verifiers
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+}
+
+impl Display for SigningKey {
+    /// The content of a key file : the public key as a comment, then the secret key
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# public key : {}", self.verifying_key())?;
+        writeln!(f, "{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = PngError;
+
+    /// Parse a key file, like [`Identity`](crate::crypto::Identity)
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = parse_key_file(s)?;
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&bytes)))
+    }
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SigningKey({})", self.verifying_key())
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = from_hex(s.trim()).ok_or_else(|| invalid("a public key is 64 hex digits"))?;
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+            .map(VerifyingKey)
+            .map_err(|_| invalid("not a valid ed25519 public key"))
+    }
+}
+
+/// Sign the critical chunks of `png` and its chunks of the `ancillary` types,
+/// and return the signature chunk to add to it. The data after `IEND`, such as
+/// a payload hidden with the trailer method, is never signed.
+///
+/// The signed bytes are the chunks covered, length, type, data and CRC, in
+/// the order of the file, after the header of the signature chunk :
+///
+/// ```text
+/// version | algorithm = 1 | public key (32) | count | count x chunk type (4) | signature (64)
+/// ```
+///
+/// Returns [`PngError::ChunkNotFound`] if no chunk has one of the `ancillary` types.
+pub fn sign(png: &Png, key: &SigningKey, ancillary: &[ChunkType]) -> Result<Chunk> {
+    let data = signature_header(key, ancillary)?;
+    for chunk_type in ancillary {
+        if !png.chunks().iter().any(|c| c.chunk_type() == chunk_type) {
+            return Err(PngError::ChunkNotFound(chunk_type.to_string()));
+        }
+    }
+    let (message, _) = signed_bytes(png, &data, ancillary);
+    signature_chunk(key, data, &message)
+}
+
+/// Like [`sign`], reading the PNG from `reader` : only the chunks covered are
+/// read, the others are skipped.
+pub fn sign_from<R: Read>(reader: R, key: &SigningKey, ancillary: &[ChunkType]) -> Result<Chunk> {
+    let data = signature_header(key, ancillary)?;
+    let mut found = vec![false; ancillary.len()];
+    let mut message = Message::new(&data);
+    let mut reader = PngReader::new(reader)?;
+    while let Some(header) = reader.next_header()? {
+        if let Some(i) = ancillary.iter().position(|t| t == header.chunk_type()) {
+            found[i] = true;
+        }
+        if covers(header.chunk_type(), ancillary) {
+            message.add(&reader.read_body()?);
+        } else {
+            reader.skip_body()?;
+        }
+    }
+    if let Some(i) = found.iter().position(|&found| !found) {
+        return Err(PngError::ChunkNotFound(ancillary[i].to_string()));
+    }
+    signature_chunk(key, data, &message.bytes)
+}
+
+/// Check the signature chunk of `png` against `key`.
+///
+/// Returns [`PngError::ChunkNotFound`] if `png` is not signed, and
+/// [`PngError::SignatureMismatch`] if it was signed by another key or if one
+/// of the chunks covered changed since.
+pub fn verify(png: &Png, key: &VerifyingKey) -> Result<Signed> {
+    let chunk = png
+        .chunks()
+        .iter()
+        .find(|c| c.chunk_type().bytes() == CHUNK_TYPE)
+        .ok_or_else(not_signed)?;
+    let (header, ancillary, signature) = parse(chunk.data(), key)?;
+    let (message, covered) = signed_bytes(png, header, &ancillary);
+    check(key, &message, signature)?;
+    Ok(Signed {
+        signer: *key,
+        ancillary,
+        covered,
+        unsigned_trailer: png.trailer().len(),
+    })
+}
+
+/// Like [`verify`], reading the PNG from `reader` twice : once to find the
+/// signature chunk, then once more for the chunks it covers. The other chunks
+/// are skipped.
+pub fn verify_from<R: Read + Seek>(mut reader: R, key: &VerifyingKey) -> Result<Signed> {
+    let start = reader.stream_position()?;
+    let mut png = PngReader::new(&mut reader)?;
+    let mut chunk = None;
+    while let Some(header) = png.next_header()? {
+        if chunk.is_none() && header.chunk_type().bytes() == CHUNK_TYPE {
+            chunk = Some(png.read_body()?);
+        }
+    }
+    let unsigned_trailer = png.copy_trailer(&mut io::sink())? as usize;
+    let chunk = chunk.ok_or_else(not_signed)?;
+    let (header, ancillary, signature) = parse(chunk.data(), key)?;
+
+    reader.seek(SeekFrom::Start(start))?;
+    let mut message = Message::new(header);
+    let mut png = PngReader::new(&mut reader)?;
+    while let Some(header) = png.next_header()? {
+        if covers(header.chunk_type(), &ancillary) {
+            message.add(&png.read_body()?);
+        }
+    }
+    check(key, &message.bytes, signature)?;
+    Ok(Signed {
+        signer: *key,
+        ancillary,
+        covered: message.covered,
+        unsigned_trailer,
+    })
+}
+
+/// The header of the signature chunk, before the signature itself
+fn signature_header(key: &SigningKey, ancillary: &[ChunkType]) -> Result<Vec<u8>> {
+    if ancillary.len() > u8::MAX as usize {
+        return Err(invalid("at most 255 ancillary chunk types can be signed"));
+    }
+    if ancillary.iter().any(|t| t.bytes() == CHUNK_TYPE) {
+        return Err(invalid("the signature can't cover itself"));
+    }
+    let mut data = vec![VERSION, ALGORITHM_ED25519];
+    data.extend_from_slice(key.verifying_key().0.as_bytes());
+    data.push(ancillary.len() as u8);
+    for chunk_type in ancillary {
+        data.extend_from_slice(&chunk_type.bytes());
+    }
+    Ok(data)
+}
+
+fn signature_chunk(key: &SigningKey, mut data: Vec<u8>, message: &[u8]) -> Result<Chunk> {
+    let signature = key.0.sign(message);
+    data.extend_from_slice(&signature.to_bytes());
+    Chunk::try_new(ChunkType::try_from(CHUNK_TYPE)?, data)
+}
+
+/// Split the data of a signature chunk made with `key` into its header, the
+/// ancillary types covered and the signature
+fn parse<'a>(data: &'a [u8], key: &VerifyingKey) -> Result<(&'a [u8], Vec<ChunkType>, &'a [u8])> {
+    let header_len = 3 + PUBLIC_KEY_LEN;
+    if data.len() < header_len {
+        return Err(invalid("signature chunk too short"));
+    }
+    if data[0] != VERSION {
+        return Err(invalid(&format!("unsupported signature version {}", data[0])));
+    }
+    if data[1] != ALGORITHM_ED25519 {
+        return Err(invalid(&format!("unsupported signature algorithm {}", data[1])));
+    }
+    let count = data[2 + PUBLIC_KEY_LEN] as usize;
+    if data.len() != header_len + 4 * count + SIGNATURE_LEN {
+        return Err(invalid("signature chunk length doesn't match its content"));
+    }
+    if data[2..2 + PUBLIC_KEY_LEN] != *key.0.as_bytes() {
+        return Err(PngError::SignatureMismatch);
+    }
+    let (header, signature) = data.split_at(data.len() - SIGNATURE_LEN);
+    let ancillary = header[header_len..]
+        .chunks_exact(4)
+        .map(|t| ChunkType::try_from([t[0], t[1], t[2], t[3]]))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((header, ancillary, signature))
+}
+
+fn check(key: &VerifyingKey, message: &[u8], signature: &[u8]) -> Result<()> {
+    let signature = ed25519_dalek::Signature::from_slice(signature)
+        .map_err(|_| PngError::SignatureMismatch)?;
+    key.0
+        .verify_strict(message, &signature)
+        .map_err(|_| PngError::SignatureMismatch)
+}
+
+/// The bytes signed, and the number of chunks they cover
+fn signed_bytes(png: &Png, header: &[u8], ancillary: &[ChunkType]) -> (Vec<u8>, usize) {
+    let mut message = Message::new(header);
+    for chunk in png.chunks() {
+        if covers(chunk.chunk_type(), ancillary) {
+            message.add(chunk);
+        }
+    }
+    (message.bytes, message.covered)
+}
+
+/// True if the signature covers the chunks of this type
+fn covers(chunk_type: &ChunkType, ancillary: &[ChunkType]) -> bool {
+    chunk_type.bytes() != CHUNK_TYPE && (chunk_type.is_critical() || ancillary.contains(chunk_type))
+}
+
+/// The signed bytes, built one chunk at a time in the order of the file
+struct Message {
+    bytes: Vec<u8>,
+    covered: usize,
+}
+
+impl Message {
+    fn new(header: &[u8]) -> Self {
+        let mut bytes = DOMAIN.to_vec();
+        bytes.extend_from_slice(header);
+        Message { bytes, covered: 0 }
+    }
+
+    fn add(&mut self, chunk: &Chunk) {
+        chunk.write_to(&mut self.bytes).expect("writing to a Vec never fails");
+        self.covered += 1;
+    }
+}
+
+fn not_signed() -> PngError {
+    PngError::ChunkNotFound(String::from_utf8_lossy(&CHUNK_TYPE).into())
+}
+
+fn invalid(reason: &str) -> PngError {
+    PngError::InvalidPayload(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            chunk("tEXt", b"Author\0me"),
+            chunk("ruSt", b"message"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    fn signed_png(key: &SigningKey, ancillary: &[&str]) -> Png {
+        let mut png = testing_png();
+        let ancillary: Vec<ChunkType> = ancillary
+            .iter()
+            .map(|t| ChunkType::from_str(t).unwrap())
+            .collect();
+        png.append_chunk(sign(&png, key, &ancillary).unwrap());
+        png
+    }
+
+    /// Same PNG with the data of the chunk at `index` replaced
+    fn altered(png: &Png, index: usize, data: &[u8]) -> Png {
+        let mut chunks = png.chunks().to_vec();
+        chunks[index] = Chunk::new(chunks[index].chunk_type().clone(), data.to_vec());
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate().unwrap();
+        let png = signed_png(&key, &["ruSt"]);
+        let signed = verify(&png, &key.verifying_key()).unwrap();
+        assert_eq!(signed.covered, 4);
+        assert_eq!(signed.ancillary, [ChunkType::from_str("ruSt").unwrap()]);
+
+        assert_eq!(signed.unsigned_trailer, 0);
+
+        // the signature still holds once serialized and parsed again
+        let mut png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert!(verify(&png, &key.verifying_key()).is_ok());
+
+        // but doesn't cover the trailer
+        png.set_trailer(b"added later".to_vec());
+        let signed = verify(&png, &key.verifying_key()).unwrap();
+        assert_eq!(signed.unsigned_trailer, 11);
+    }
+
+    #[test]
+    fn test_altered_chunks() {
+        let key = SigningKey::generate().unwrap();
+        let png = signed_png(&key, &["ruSt"]);
+        let public = key.verifying_key();
+
+        for index in [0, 2, 3] {
+            let png = altered(&png, index, b"changed");
+            assert!(matches!(verify(&png, &public), Err(PngError::SignatureMismatch)));
+        }
+        // tEXt is not covered
+        assert!(verify(&altered(&png, 1, b"Author\0other"), &public).is_ok());
+
+        let mut removed = signed_png(&key, &["ruSt"]);
+        removed.remove_chunk("ruSt").unwrap();
+        assert!(matches!(verify(&removed, &public), Err(PngError::SignatureMismatch)));
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let key = SigningKey::generate().unwrap();
+        let other = SigningKey::generate().unwrap();
+        let png = signed_png(&key, &[]);
+        let result = verify(&png, &other.verifying_key());
+        assert!(matches!(result, Err(PngError::SignatureMismatch)));
+    }
+
+    #[test]
+    fn test_not_signed() {
+        let key = SigningKey::generate().unwrap();
+        let result = verify(&testing_png(), &key.verifying_key());
+        assert!(matches!(result, Err(PngError::ChunkNotFound(t)) if t == "siGN"));
+
+        let missing = [ChunkType::from_str("otHr").unwrap()];
+        let result = sign(&testing_png(), &key, &missing);
+        assert!(matches!(result, Err(PngError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_sign_and_verify_from_reader() {
+        let key = SigningKey::generate().unwrap();
+        let rust = [ChunkType::from_str("ruSt").unwrap()];
+        let mut png = testing_png();
+        let bytes = png.as_bytes();
+        // ed25519 signatures are deterministic : both ways give the same chunk
+        let chunk = sign_from(bytes.as_slice(), &key, &rust).unwrap();
+        assert_eq!(chunk, sign(&png, &key, &rust).unwrap());
+        let missing = [ChunkType::from_str("otHr").unwrap()];
+        let result = sign_from(bytes.as_slice(), &key, &missing);
+        assert!(matches!(result, Err(PngError::ChunkNotFound(t)) if t == "otHr"));
+
+        png.append_chunk(chunk);
+        png.set_trailer(b"added later".to_vec());
+        let signed = verify_from(io::Cursor::new(png.as_bytes()), &key.verifying_key()).unwrap();
+        assert_eq!(signed, verify(&png, &key.verifying_key()).unwrap());
+        assert_eq!(signed.covered, 4);
+        assert_eq!(signed.unsigned_trailer, 11);
+
+        let altered = altered(&png, 2, b"changed");
+        let result = verify_from(io::Cursor::new(altered.as_bytes()), &key.verifying_key());
+        assert!(matches!(result, Err(PngError::SignatureMismatch)));
+        let result = verify_from(io::Cursor::new(bytes), &key.verifying_key());
+        assert!(matches!(result, Err(PngError::ChunkNotFound(t)) if t == "siGN"));
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let key = SigningKey::generate().unwrap();
+        let parsed = SigningKey::from_str(&key.to_string()).unwrap();
+        assert_eq!(parsed.verifying_key(), key.verifying_key());
+        let public = key.verifying_key().to_string();
+        assert_eq!(VerifyingKey::from_str(&public).unwrap(), key.verifying_key());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::idat;
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::{PngError, Result};
+
+/// First bytes of the data hidden after the zlib stream
+pub const MAGIC: [u8; 5] = *b"PNGMZ";
+/// Version of the layout of the hidden data
+pub const VERSION: u8 = 1;
+/// magic, version, length (u32)
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+/// Hide `data` in the IDAT chunks of `png`, after the end of their zlib
+/// stream, and return the new image. Decoders stop at the checksum of the
+/// stream, so the pixels don't change : this is checked by [`verify`] before
+/// the image is returned. The image data is cut again into chunks as large as
+/// the largest IDAT, the other chunks are kept. The data is stored as :
+///
+/// ```text
+/// zlib stream | "PNGMZ" | version | length (u32) | data
+/// ```
+///
+/// Returns [`PngError::InvalidPayload`] if bytes already follow the zlib
+/// stream, they may be the data of another tool.
+pub fn embed(png: &Png, data: &[u8]) -> Result<Png> {
+    let mut image_data = idat::concat(png)?;
+    let stream_len = idat::stream_len(&image_data)?;
+    if stream_len < image_data.len() {
+        return Err(PngError::InvalidPayload(format!(
+            "{} bytes already follow the zlib stream of IDAT",
+            image_data.len() - stream_len
+        )));
+    }
+    let len = u32::try_from(data.len()).map_err(|_| PngError::PayloadTooLarge {
+        limit: u32::MAX as u64,
+    })?;
+    image_data.extend_from_slice(&MAGIC);
+    image_data.push(VERSION);
+    image_data.extend_from_slice(&len.to_be_bytes());
+    image_data.extend_from_slice(data);
+    let embedded = idat::replace(png, &image_data)?;
+    verify(png, &embedded)?;
+    Ok(embedded)
+}
+
+/// Read the data hidden by [`embed`] after the zlib stream of `png`
+pub fn extract(png: &Png) -> Result<Vec<u8>> {
+    let image_data = idat::concat(png)?;
+    let slack = &image_data[idat::stream_len(&image_data)?..];
+    if slack.is_empty() {
+        return Err(PngError::InvalidPayload(
+            "no data after the zlib stream of IDAT".to_string(),
+        ));
+    }
+    if slack.len() < HEADER_LEN || slack[..MAGIC.len()] != MAGIC {
+        return Err(PngError::InvalidPayload(
+            "the data after the zlib stream of IDAT was not hidden by pngme".to_string(),
+        ));
+    }
+    if slack[MAGIC.len()] != VERSION {
+        let version = slack[MAGIC.len()];
+        return Err(PngError::InvalidPayload(format!("unsupported slack version {}", version)));
+    }
+    let len = u32::from_be_bytes(slack[MAGIC.len() + 1..HEADER_LEN].try_into().unwrap()) as usize;
+    slack[HEADER_LEN..]
+        .get(..len)
+        .map(|data| data.to_vec())
+        .ok_or_else(|| {
+            PngError::InvalidPayload(format!(
+                "{} bytes hidden after the zlib stream, {} found",
+                len,
+                slack.len() - HEADER_LEN
+            ))
+        })
+}
+
+/// Check that `before` and `after` decode to the same image : same header,
+/// and bit for bit the same samples once inflated and unfiltered. Returns
+/// [`PngError::InvalidImageData`] otherwise.
+pub fn verify(before: &Png, after: &Png) -> Result<()> {
+    let before = Pixels::from_png(before)?;
+    let after = Pixels::from_png(after)?;
+    if before.ihdr() != after.ihdr() {
+        return Err(PngError::InvalidImageData("the header changed".to_string()));
+    }
+    match before.data().iter().zip(after.data()).position(|(a, b)| a != b) {
+        Some(offset) => Err(PngError::InvalidImageData(format!(
+            "the pixels changed, from byte {} of the scanlines",
+            offset
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::testing_png;
+
+    #[test]
+    fn test_embed_and_extract() {
+        for interlaced in [false, true] {
+            let mut png = testing_png(13, 11, 6, interlaced);
+            png.set_trailer(b"trailer".to_vec());
+            let data = vec![42u8; 3 * idat::chunk_len(&png)];
+            let embedded = embed(&png, &data).unwrap();
+            assert_eq!(extract(&embedded).unwrap(), data);
+            assert_eq!(embedded.trailer(), b"trailer");
+
+            // the zlib stream itself is untouched
+            let before = idat::concat(&png).unwrap();
+            let after = idat::concat(&embedded).unwrap();
+            assert_eq!(after[..before.len()], before[..]);
+            assert_eq!(idat::stream_len(&after).unwrap(), before.len());
+            verify(&png, &embedded).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_occupied_or_empty_slack() {
+        let png = testing_png(8, 8, 2, false);
+        assert!(matches!(extract(&png), Err(PngError::InvalidPayload(_))));
+
+        let embedded = embed(&png, b"message").unwrap();
+        assert!(matches!(embed(&embedded, b"again"), Err(PngError::InvalidPayload(_))));
+
+        let mut image_data = idat::concat(&png).unwrap();
+        image_data.extend_from_slice(b"other tool");
+        let other = idat::replace(&png, &image_data).unwrap();
+        assert!(matches!(extract(&other), Err(PngError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn test_verify() {
+        let png = testing_png(8, 8, 2, false);
+        let mut pixels = Pixels::from_png(&png).unwrap();
+        pixels.data_mut()[70] ^= 1;
+        let changed = pixels.replace_in(&png).unwrap();
+        let result = verify(&png, &changed);
+        assert!(matches!(result, Err(PngError::InvalidImageData(m)) if m.contains("byte 70")));
+        // other chunking and compression, same pixels
+        pixels.data_mut()[70] ^= 1;
+        verify(&png, &pixels.replace_in(&png).unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use crate::chunk::Chunk;
+use crate::crypto::random_bytes;
+use crate::{PngError, Result};
+
+/// First bytes of each piece of a split payload
+pub const MAGIC: [u8; 5] = *b"PNGMS";
+/// Version of the layout of the pieces. Version 1 had no name.
+pub const VERSION: u8 = 2;
+/// Largest chunk data written by default before a payload is split
+pub const DEFAULT_PIECE_LEN: usize = 64 * 1024;
+const ID_LEN: usize = 8;
+/// magic, version, id, sequence number, count, name length, without the name
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + ID_LEN + 4 + 4 + 1;
+
+/// One piece of a payload split across several chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece<'a> {
+    /// Random identifier shared by the pieces of a payload
+    pub id: [u8; ID_LEN],
+    /// Position of the piece, from 0
+    pub seq: u32,
+    /// Number of pieces of the payload
+    pub total: u32,
+    /// Name of the message, repeated in each piece
+    pub name: Option<&'a str>,
+    pub data: &'a [u8],
+}
+
+impl<'a> Piece<'a> {
+    /// Parse the data of a chunk, `None` if it is not a piece
+    pub fn parse(chunk_data: &'a [u8]) -> Result<Option<Piece<'a>>> {
+        if !chunk_data.starts_with(&MAGIC) {
+            return Ok(None);
+        }
+        let header_len = match chunk_data.get(MAGIC.len()) {
+            Some(1) => HEADER_LEN - 1,
+            Some(&VERSION) => HEADER_LEN,
+            Some(version) => {
+                return Err(invalid(&format!("unsupported piece version {}", version)))
+            }
+            None => return Err(invalid("truncated piece header")),
+        };
+        if chunk_data.len() < header_len {
+            return Err(invalid("truncated piece header"));
+        }
+        let mut index = MAGIC.len() + 1;
+        let mut id = [0u8; ID_LEN];
+        id.copy_from_slice(&chunk_data[index..index + ID_LEN]);
+        index += ID_LEN;
+        let seq = u32::from_be_bytes(chunk_data[index..index + 4].try_into().unwrap());
+        let total = u32::from_be_bytes(chunk_data[index + 4..index + 8].try_into().unwrap());
+        if seq >= total {
+            return Err(invalid(&format!("piece {} of a payload of {}", seq + 1, total)));
+        }
+        let name_len = match header_len {
+            HEADER_LEN => chunk_data[index + 8] as usize,
+            _ => 0,
+        };
+        if chunk_data.len() < header_len + name_len {
+            return Err(invalid("truncated piece header"));
+        }
+        let name = match name_len {
+            0 => None,
+            _ => Some(
+                std::str::from_utf8(&chunk_data[header_len..header_len + name_len])
+                    .map_err(|_| invalid("message name is not valid UTF-8"))?,
+            ),
+        };
+        Ok(Some(Piece {
+            id,
+            seq,
+            total,
+            name,
+            data: &chunk_data[header_len + name_len..],
+        }))
+    }
+
+    /// Identifier of the payload, in hex
+    pub fn id_hex(&self) -> String {
+        self.id.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Chunk data to store `payload` in chunks of at most `piece_len` bytes.
+///
+/// A payload which fits and has no `name` is returned as is, otherwise each
+/// piece starts with a header holding the id of the payload, its position,
+/// the count and the name :
+///
+/// ```text
+/// "PNGMS" | version | id (8) | seq (u32) | total (u32) | name length (u8) | name | data
+/// ```
+pub fn split(payload: &[u8], piece_len: usize, name: Option<&str>) -> Result<Vec<Vec<u8>>> {
+    if piece_len > Chunk::MAX_LENGTH as usize {
+        return Err(invalid(&format!(
+            "pieces of {} bytes, a chunk holds {} at most",
+            piece_len,
+            Chunk::MAX_LENGTH
+        )));
+    }
+    if payload.len() <= piece_len && name.is_none() {
+        return Ok(vec![payload.to_vec()]);
+    }
+    if name == Some("") {
+        return Err(invalid("empty message name"));
+    }
+    let name = name.unwrap_or("").as_bytes();
+    if name.len() > u8::MAX as usize {
+        return Err(invalid("message name longer than 255 bytes"));
+    }
+    let header_len = HEADER_LEN + name.len();
+    if piece_len <= header_len {
+        return Err(invalid(&format!(
+            "pieces of {} bytes can't hold the {} bytes header",
+            piece_len, header_len
+        )));
+    }
+    let mut pieces: Vec<&[u8]> = payload.chunks(piece_len - header_len).collect();
+    if pieces.is_empty() {
+        pieces.push(&[]);
+    }
+    let total = u32::try_from(pieces.len()).map_err(|_| invalid("too many pieces"))?;
+    let mut id = [0u8; ID_LEN];
+    random_bytes(&mut id)?;
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(seq, data)| {
+            let mut piece = Vec::with_capacity(header_len + data.len());
+            piece.extend_from_slice(&MAGIC);
+            piece.push(VERSION);
+            piece.extend_from_slice(&id);
+            piece.extend_from_slice(&(seq as u32).to_be_bytes());
+            piece.extend_from_slice(&total.to_be_bytes());
+            piece.push(name.len() as u8);
+            piece.extend_from_slice(name);
+            piece.extend_from_slice(data);
+            piece
+        })
+        .collect())
+}
+
+/// Rebuild the first payload found in `chunks`, the data of the chunks of one
+/// type in the order of the file.
+///
+/// If the first chunk is a piece, the pieces of the same payload must follow
+/// in order, other chunks may be in between. Returns
+/// [`PngError::InvalidPayload`] naming the piece missing or out of order.
+pub fn reassemble<'a, I>(chunks: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut chunks = chunks.into_iter();
+    let first = chunks.next().ok_or_else(|| invalid("no chunk"))?;
+    let first_piece = match Piece::parse(first)? {
+        None => return Ok(first.to_vec()),
+        Some(p) => p,
+    };
+    if first_piece.seq != 0 {
+        return Err(invalid(&format!(
+            "payload {} starts with piece {} of {}, piece 1 is missing or out of order",
+            first_piece.id_hex(),
+            first_piece.seq + 1,
+            first_piece.total
+        )));
+    }
+    let mut payload = first_piece.data.to_vec();
+    let mut expected = 1;
+    for chunk in chunks {
+        if expected == first_piece.total {
+            break;
+        }
+        let piece = match Piece::parse(chunk) {
+            Ok(Some(p)) if p.id == first_piece.id => p,
+            _ => continue,
+        };
+        if piece.total != first_piece.total {
+            return Err(invalid(&format!(
+                "piece {} of payload {} counts {} pieces instead of {}",
+                piece.seq + 1,
+                piece.id_hex(),
+                piece.total,
+                first_piece.total
+            )));
+        }
+        if piece.seq != expected {
+            return Err(invalid(&format!(
+                "piece {} of {} of payload {} found where piece {} was expected",
+                piece.seq + 1,
+                piece.total,
+                piece.id_hex(),
+                expected + 1
+            )));
+        }
+        payload.extend_from_slice(piece.data);
+        expected += 1;
+    }
+    if expected != first_piece.total {
+        return Err(invalid(&format!(
+            "piece {} of {} of payload {} is missing",
+            expected + 1,
+            first_piece.total,
+            first_piece.id_hex()
+        )));
+    }
+    Ok(payload)
+}
+
+fn invalid(reason: &str) -> PngError {
+    PngError::InvalidPayload(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn reassemble_all(pieces: &[Vec<u8>]) -> Result<Vec<u8>> {
+        reassemble(pieces.iter().map(|p| p.as_slice()))
+    }
+
+    #[test]
+    fn test_small_payload_is_not_split() {
+        let pieces = split(b"message", 100, None).unwrap();
+        assert_eq!(pieces, [b"message".to_vec()]);
+        assert_eq!(reassemble_all(&pieces).unwrap(), b"message");
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let data = payload(1000);
+        let pieces = split(&data, 100, None).unwrap();
+        assert_eq!(pieces.len(), 13);
+        assert!(pieces.iter().all(|p| p.len() <= 100));
+        let first = Piece::parse(&pieces[0]).unwrap().unwrap();
+        let last = Piece::parse(&pieces[12]).unwrap().unwrap();
+        assert_eq!((first.seq, first.total), (0, 13));
+        assert_eq!((last.seq, last.id), (12, first.id));
+        assert_eq!(reassemble_all(&pieces).unwrap(), data);
+    }
+
+    #[test]
+    fn test_other_chunks_in_between() {
+        let data = payload(300);
+        let other = split(&payload(300), 100, None).unwrap();
+        let pieces = split(&data, 100, None).unwrap();
+        let mixed = vec![
+            pieces[0].clone(),
+            other[0].clone(),
+            b"plain".to_vec(),
+            pieces[1].clone(),
+            other[1].clone(),
+            pieces[2].clone(),
+            pieces[3].clone(),
+        ];
+        assert_eq!(reassemble_all(&mixed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_missing_piece() {
+        let mut pieces = split(&payload(300), 100, None).unwrap();
+        pieces.pop();
+        let error = reassemble_all(&pieces).unwrap_err().to_string();
+        assert!(error.contains("piece 4 of 4"), "{}", error);
+
+        let mut pieces = split(&payload(300), 100, None).unwrap();
+        pieces.remove(1);
+        let error = reassemble_all(&pieces).unwrap_err().to_string();
+        assert!(error.contains("piece 2 was expected"), "{}", error);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut pieces = split(&payload(300), 100, None).unwrap();
+        pieces.swap(1, 2);
+        assert!(matches!(reassemble_all(&pieces), Err(PngError::InvalidPayload(_))));
+        pieces.swap(0, 1);
+        let error = reassemble_all(&pieces).unwrap_err().to_string();
+        assert!(error.contains("piece 1 is missing"), "{}", error);
+    }
+
+    #[test]
+    fn test_invalid_piece_len() {
+        assert!(split(&payload(300), HEADER_LEN, None).is_err());
+        assert!(split(&payload(300), Chunk::MAX_LENGTH as usize + 1, None).is_err());
+    }
+
+    #[test]
+    fn test_named_payload() {
+        let pieces = split(b"message", 100, Some("notes")).unwrap();
+        assert_eq!(pieces.len(), 1);
+        let piece = Piece::parse(&pieces[0]).unwrap().unwrap();
+        assert_eq!(piece.name, Some("notes"));
+        assert_eq!(piece.data, b"message");
+        assert_eq!(reassemble_all(&pieces).unwrap(), b"message");
+        assert!(split(b"message", 100, Some(&"n".repeat(256))).is_err());
+        assert!(split(b"message", 100, Some("")).is_err());
+    }
+
+    #[test]
+    fn test_version_1() {
+        let mut piece = b"PNGMS\x01".to_vec();
+        piece.extend_from_slice(&[9; 8]);
+        piece.extend_from_slice(&0u32.to_be_bytes());
+        piece.extend_from_slice(&1u32.to_be_bytes());
+        piece.extend_from_slice(b"data");
+        let parsed = Piece::parse(&piece).unwrap().unwrap();
+        assert_eq!(parsed.name, None);
+        assert_eq!(parsed.data, b"data");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::chunk::Chunk;
+use crate::ihdr::Ihdr;
+use crate::png::Png;
+use serde::Serialize;
+
+/// What is worth knowing about one chunk of a PNG, without its data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkSummary {
+    pub index: usize,
+    /// Byte offset of the chunk from the start of the file
+    pub offset: usize,
+    pub chunk_type: String,
+    pub length: u32,
+    pub crc: u32,
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+    /// Decoded content for the known chunks : IHDR, tEXt and tIME
+    pub decoded: Option<String>,
+}
+
+impl ChunkSummary {
+    pub fn new(index: usize, offset: usize, chunk: &Chunk) -> ChunkSummary {
+        let chunk_type = chunk.chunk_type();
+        ChunkSummary {
+            index,
+            offset,
+            chunk_type: chunk_type.to_string(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            decoded: decode(chunk),
+        }
+    }
+}
+
+impl Png {
+    /// Summary of every chunk, with its offset in the serialized PNG
+    pub fn summaries(&self) -> Vec<ChunkSummary> {
+        let mut offset = Png::STANDARD_HEADER.len();
+        self.chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let summary = ChunkSummary::new(index, offset, chunk);
+                offset += chunk.length() as usize + Chunk::SIZE_WITHOUT_DATA;
+                summary
+            })
+            .collect()
+    }
+}
+
+/// Human readable content of the known chunks
+fn decode(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    match &chunk.chunk_type().bytes() {
+        b"IHDR" => Some(match Ihdr::try_from(chunk) {
+            Ok(ihdr) => ihdr.to_string(),
+            Err(e) => e.to_string(),
+        }),
+        b"tEXt" => {
+            let separator = data.iter().position(|&b| b == 0)?;
+            Some(format!(
+                "{} : {}",
+                latin1(&data[..separator]),
+                latin1(&data[separator + 1..])
+            ))
+        }
+        b"tIME" if data.len() == 7 => {
+            let year = u16::from_be_bytes([data[0], data[1]]);
+            Some(format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                year, data[2], data[3], data[4], data[5], data[6]
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// tEXt keyword and text are ISO 8859-1
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_summaries() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 2, 0, 0, 0, 3, 8, 2, 0, 0, 0]),
+            chunk("tEXt", b"Author\0J\xe9r\xf4me"),
+            chunk("tIME", &[7, 231, 4, 15, 17, 25, 33]),
+            chunk("ruSt", b"secret"),
+            chunk("IEND", b""),
+        ]);
+        let summaries = png.summaries();
+
+        assert_eq!(summaries.len(), 5);
+        assert_eq!(summaries[0].offset, 8);
+        assert_eq!(summaries[1].offset, 8 + 25);
+        assert_eq!(
+            summaries[0].decoded.as_deref(),
+            Some("2x3, 8 bits, RGB, not interlaced")
+        );
+        assert_eq!(summaries[1].decoded.as_deref(), Some("Author : Jérôme"));
+        assert_eq!(
+            summaries[2].decoded.as_deref(),
+            Some("2023-04-15 17:25:33 UTC")
+        );
+        assert_eq!(summaries[3].decoded, None);
+        assert!(!summaries[3].critical);
+        assert!(!summaries[3].public);
+        assert!(summaries[3].safe_to_copy);
+        assert_eq!(summaries[3].crc, png.chunks()[3].crc());
+        assert_eq!(summaries[4].index, 4);
+    }
+}
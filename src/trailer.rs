@@ -0,0 +1,82 @@
+use crate::{PngError, Result};
+
+/// First bytes of the data hidden after `IEND`
+pub const MAGIC: [u8; 5] = *b"PNGMT";
+/// Version of the layout of the hidden data
+pub const VERSION: u8 = 1;
+/// magic, version, length (u32)
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+/// The trailer holding `data`, to write after `IEND`. It is stored as :
+///
+/// ```text
+/// "PNGMT" | version | length (u32) | data
+/// ```
+///
+/// so that the data of another tool isn't mistaken for a payload.
+pub fn frame(data: &[u8]) -> Result<Vec<u8>> {
+    let len = u32::try_from(data.len()).map_err(|_| PngError::PayloadTooLarge {
+        limit: u32::MAX as u64,
+    })?;
+    let mut trailer = Vec::with_capacity(HEADER_LEN + data.len());
+    trailer.extend_from_slice(&MAGIC);
+    trailer.push(VERSION);
+    trailer.extend_from_slice(&len.to_be_bytes());
+    trailer.extend_from_slice(data);
+    Ok(trailer)
+}
+
+/// Read the data framed by [`frame`] in `trailer`, the bytes after `IEND`.
+///
+/// Returns [`PngError::NoTrailer`] if `trailer` is empty, and
+/// [`PngError::InvalidPayload`] if it was not written by pngme or is truncated.
+pub fn unframe(trailer: &[u8]) -> Result<&[u8]> {
+    if trailer.is_empty() {
+        return Err(PngError::NoTrailer);
+    }
+    if trailer.len() < HEADER_LEN || trailer[..MAGIC.len()] != MAGIC {
+        return Err(PngError::InvalidPayload(
+            "the data after IEND was not hidden by pngme".to_string(),
+        ));
+    }
+    if trailer[MAGIC.len()] != VERSION {
+        let version = trailer[MAGIC.len()];
+        return Err(PngError::InvalidPayload(format!("unsupported trailer version {}", version)));
+    }
+    let len = u32::from_be_bytes(trailer[MAGIC.len() + 1..HEADER_LEN].try_into().unwrap()) as usize;
+    trailer[HEADER_LEN..].get(..len).ok_or_else(|| {
+        PngError::InvalidPayload(format!(
+            "{} bytes hidden after IEND, {} found",
+            len,
+            trailer.len() - HEADER_LEN
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_and_unframe() {
+        let trailer = frame(b"payload").unwrap();
+        assert!(trailer.starts_with(&MAGIC));
+        assert_eq!(trailer.len(), HEADER_LEN + 7);
+        assert_eq!(unframe(&trailer).unwrap(), b"payload");
+        assert_eq!(unframe(&frame(b"").unwrap()).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_foreign_or_truncated_trailer() {
+        assert!(matches!(unframe(b""), Err(PngError::NoTrailer)));
+        let result = unframe(b"PNGMPpayload from another version");
+        assert!(matches!(result, Err(PngError::InvalidPayload(m)) if m.contains("not hidden")));
+
+        let trailer = frame(b"payload").unwrap();
+        let result = unframe(&trailer[..trailer.len() - 1]);
+        assert!(matches!(result, Err(PngError::InvalidPayload(_))));
+        let mut other_version = trailer.clone();
+        other_version[MAGIC.len()] = 9;
+        assert!(matches!(unframe(&other_version), Err(PngError::InvalidPayload(_))));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::reader::PngReader;
+use crate::Result;
+use std::io::{Read, Write};
+
+/// Writes a PNG to any `Write` one chunk at a time.
+/// The signature is written when the writer is created, each chunk is then
+/// written as soon as it is given, so nothing is buffered besides what the
+/// underlying writer does.
+///
+/// ```no_run
+/// use pngme::{PngReader, PngWriter};
+/// use std::fs::File;
+/// use std::io::{BufReader, BufWriter};
+///
+/// let reader = PngReader::new(BufReader::new(File::open("file.png")?))?;
+/// let mut writer = PngWriter::new(BufWriter::new(File::create("copy.png")?))?;
+/// for chunk in reader {
+///     writer.write_chunk(&chunk?)?;
+/// }
+/// writer.finish()?;
+/// # Ok::<(), pngme::Error>(())
+/// ```
+pub struct PngWriter<W: Write> {
+    writer: W,
+    offset: usize,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Write the PNG signature
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter {
+            writer,
+            offset: Png::STANDARD_HEADER.len(),
+        })
+    }
+
+    /// Number of bytes written so far, signature included
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Write one chunk
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        chunk.write_to(&mut self.writer)?;
+        self.offset += chunk.length() as usize + Chunk::SIZE_WITHOUT_DATA;
+        Ok(())
+    }
+
+    /// Copy the chunk whose header was just read by `reader`, byte for byte
+    pub fn copy_from<R: Read>(&mut self, reader: &mut PngReader<R>) -> Result<()> {
+        let start = reader.offset();
+        reader.copy_body(&mut self.writer)?;
+        self.offset += reader.offset() - start;
+        Ok(())
+    }
+
+    /// Write bytes after the chunks, once `IEND` is written
+    pub fn write_trailer(&mut self, trailer: &[u8]) -> Result<()> {
+        self.writer.write_all(trailer)?;
+        self.offset += trailer.len();
+        Ok(())
+    }
+
+    /// Copy the bytes `reader` finds after `IEND`, once its chunks are read
+    pub fn copy_trailer_from<R: Read>(&mut self, reader: &mut PngReader<R>) -> Result<()> {
+        self.offset += reader.copy_trailer(&mut self.writer)? as usize;
+        Ok(())
+    }
+
+    /// Write every chunk of the iterator
+    pub fn write_chunks<'a, I>(&mut self, chunks: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a Chunk>,
+    {
+        for chunk in chunks {
+            self.write_chunk(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Flush and give back the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"middle".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn test_write_chunks() {
+        let chunks = testing_chunks();
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_chunks(&chunks).unwrap();
+        assert_eq!(writer.offset(), 8 + 12 + 5 + 12 + 6 + 12);
+        let bytes = writer.finish().unwrap();
+
+        assert_eq!(bytes, Png::from_chunks(chunks).as_bytes());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chunks = testing_chunks();
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_chunks(&chunks).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let read: Vec<Chunk> = PngReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read, chunks);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut chunks = testing_chunks();
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailer(b"trailer".to_vec());
+        let bytes = png.as_bytes();
+
+        let mut reader = PngReader::new(bytes.as_slice()).unwrap();
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        while reader.next_header().unwrap().is_some() {
+            writer.copy_from(&mut reader).unwrap();
+        }
+        writer.copy_trailer_from(&mut reader).unwrap();
+        assert_eq!(writer.offset(), bytes.len());
+        assert_eq!(writer.finish().unwrap(), bytes);
+    }
+}
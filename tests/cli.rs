@@ -0,0 +1,109 @@
+use pngme::{Chunk, ChunkType, Png};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+
+fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+}
+
+/// A small valid image : one grey pixel
+fn testing_png() -> Png {
+    Png::from_chunks(vec![
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+        chunk("IDAT", &[0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01]),
+        chunk("IEND", b""),
+    ])
+}
+
+/// Run pngme with `args`, `stdin` written to its standard input
+fn pngme(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pngme"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_json_with_png_on_stdout() {
+    let input = testing_png().as_bytes();
+    let args = ["--format", "json", "encode", "-f", "-", "-c", "ruSt", "-m", "hi"];
+    let output = pngme(&args, &input);
+    assert!(output.status.success());
+
+    // stdout holds the image alone, the result goes to stderr
+    let png = Png::try_from(output.stdout.as_slice()).unwrap();
+    assert!(png.trailer().is_empty());
+    assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hi");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("\"output\": \"-\""), "{}", stderr);
+}
+
+#[test]
+fn test_failed_edit_writes_nothing() {
+    let input = testing_png().as_bytes();
+    let output = pngme(&["remove", "-f", "-", "-c", "ziPp", "-o", "-"], &input);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(output.stdout.is_empty());
+
+    // an existing output is left untouched, and no temporary file remains
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.png");
+    std::fs::write(&path, b"previous").unwrap();
+    let out = path.to_str().unwrap();
+    let output = pngme(&["remove", "-f", "-", "-c", "ziPp", "-o", out], &input);
+    assert_eq!(output.status.code(), Some(6));
+    assert_eq!(std::fs::read(&path).unwrap(), b"previous");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_sign_and_verify_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_path = dir.path().join("key");
+    let key = pngme::signature::SigningKey::generate().unwrap();
+    std::fs::write(&key_path, key.to_string()).unwrap();
+    let key_path = key_path.to_str().unwrap();
+
+    let input = testing_png().as_bytes();
+    let output = pngme(&["sign", "-f", "-", "-k", key_path], &input);
+    assert!(output.status.success());
+    let signed = output.stdout;
+    assert!(Png::try_from(signed.as_slice()).unwrap().chunk_by_type("siGN").is_some());
+
+    // signing again replaces the signature
+    let output = pngme(&["sign", "-f", "-", "-k", key_path], &signed);
+    let png = Png::try_from(output.stdout.as_slice()).unwrap();
+    assert_eq!(png.chunks_by_type("siGN").count(), 1);
+
+    let pubkey = key.verifying_key().to_string();
+    let output = pngme(&["verify", "-f", "-", "-p", &pubkey], &signed);
+    assert!(output.status.success());
+    let output = pngme(&["verify", "-f", "-", "-p", &pubkey], &input);
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn test_capacity_of_indexed_image() {
+    let png = Png::from_chunks(vec![
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0]),
+        chunk("PLTE", &[0, 0, 0]),
+        chunk("IDAT", &[0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01]),
+        chunk("IEND", b""),
+    ]);
+    let output = pngme(&["--format", "json", "capacity", "-f", "-"], &png.as_bytes());
+    assert!(output.status.success());
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let methods = value["methods"].as_array().unwrap();
+    let unsupported = |name: &str| {
+        let method = methods.iter().find(|m| m["method"] == name).unwrap();
+        method["unsupported"].as_str().unwrap().to_string()
+    };
+    assert!(unsupported("palette").contains("not implemented"));
+    assert!(unsupported("lsb").contains("palette"));
+}